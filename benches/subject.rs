@@ -0,0 +1,78 @@
+//! subject benchmarks evaluating formulas against a large subject.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#[macro_use]
+extern crate nock;
+
+use std::time::{Duration, Instant};
+
+use nock::nock::compute;
+use nock::parser::{Noun, atom};
+
+// tree builds a balanced tree of the given depth with numbered leaves.
+fn tree(depth: u32, next: &mut u64) -> Noun {
+    if depth == 0 {
+        *next += 1;
+        return atom(*next);
+    }
+    let head = tree(depth - 1, next);
+    let tail = tree(depth - 1, next);
+    cell!(head, tail)
+}
+
+// deep_copy rebuilds every cell of a noun. This is what evaluation used to
+// pay each time it cloned the subject.
+fn deep_copy(noun: &Noun) -> Noun {
+    match noun.as_cell() {
+        Some((head, tail)) => cell!(deep_copy(head), deep_copy(tail)),
+        None => noun.clone(),
+    }
+}
+
+fn report(name: &str, iters: u32, elapsed: Duration) {
+    let nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+    println!("{:<40} {:>8} iters {:>12} ns/iter", name, iters, nanos / iters as u64);
+}
+
+fn bench<F: FnMut()>(name: &str, iters: u32, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    report(name, iters, start.elapsed());
+}
+
+fn main() {
+    let depth = 16;
+    let subject = tree(depth, &mut 0);
+    // The first leaf of the tree and the tail of the root.
+    let axes = vec![1u64 << depth, 3];
+    let formulas = vec![
+        // *[a 0 b]
+        cell!(atom(0), atom(axes[0])),
+        // *[a 8 [0 3] 7 [0 3] 0 2] pushes and composes against the subject.
+        cell!(atom(8), cell!(atom(0), atom(axes[1])),
+              atom(7), cell!(atom(0), atom(3)), atom(0), atom(2)),
+    ];
+    let iters = 200;
+
+    for (i, formula) in formulas.iter().enumerate() {
+        bench(&format!("formula {} shared subject", i), iters, || {
+            compute(cell!(subject.clone(), formula.clone())).unwrap();
+        });
+        bench(&format!("formula {} deep copied subject", i), iters, || {
+            compute(cell!(deep_copy(&subject), formula.clone())).unwrap();
+        });
+    }
+}
@@ -0,0 +1,69 @@
+//! vm benchmarks the bytecode VM against walking the formula tree.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#[macro_use]
+extern crate nock;
+
+use std::time::{Duration, Instant};
+
+use nock::nock::{Interpreter, Mode, Spec};
+use nock::parser::{Noun, atom};
+
+fn report(name: &str, iters: u32, elapsed: Duration) {
+    let nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+    println!("{:<40} {:>8} iters {:>12} ns/iter", name, iters, nanos / iters as u64);
+}
+
+fn bench<F: FnMut()>(name: &str, iters: u32, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    report(name, iters, start.elapsed());
+}
+
+// dec builds a gate that decrements its sample by counting up to it.
+fn dec(n: u64) -> Noun {
+    // [8 [1 0] 8 [1 6 [5 [0 30] 4 0 6] [0 6] 9 2 10 [6 4 0 6] 0 1] 9 2 0 1]
+    let loop_arm = cell!(atom(6),
+                         cell!(atom(5), cell!(atom(0), atom(30)), atom(4), atom(0), atom(6)),
+                         cell!(atom(0), atom(6)),
+                         atom(9),
+                         atom(2),
+                         atom(10),
+                         cell!(atom(6), atom(4), atom(0), atom(6)),
+                         atom(0),
+                         atom(1));
+    let battery = cell!(atom(8),
+                        cell!(atom(1), atom(0)),
+                        atom(8),
+                        cell!(atom(1), loop_arm),
+                        atom(9),
+                        atom(2),
+                        atom(0),
+                        atom(1));
+    cell!(cell!(battery, atom(n), atom(0)), atom(9), atom(2), atom(0), atom(1))
+}
+
+fn main() {
+    let iters = 20;
+    let expr = dec(10000);
+    for mode in vec![Mode::Tree, Mode::Bytecode] {
+        let mut interpreter = Interpreter::new(Spec::Nock4K);
+        interpreter.set_mode(mode);
+        bench(&format!("dec 10000 {}", mode), iters, || {
+            interpreter.compute(expr.clone()).unwrap();
+        });
+    }
+}
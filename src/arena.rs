@@ -0,0 +1,214 @@
+//! arena implements an optional hash consing arena for nouns.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use atom::Atom;
+use parser::Noun;
+
+static NEXT_ARENA: AtomicUsize = AtomicUsize::new(1);
+
+/// InternId identifies a Cell interned by an Arena.
+///
+/// Two cells interned by the same Arena are equal if and only if their
+/// InternIds are equal.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub struct InternId {
+    pub arena: usize,
+    pub index: usize,
+}
+
+// Key is how a head or tail is identified when looking up a Cell. Interned
+// cells are identified by index so the lookup never walks a tree.
+#[derive(PartialEq,Eq,Hash)]
+enum Key {
+    Atom(Atom),
+    Cell(usize),
+}
+
+/// Arena hash conses nouns.
+///
+/// Every distinct noun interned by an Arena is stored exactly once so
+/// equality between interned nouns is an id compare. The Arena keeps
+/// everything it interns alive until it is dropped.
+pub struct Arena {
+    id: usize,
+    cells: HashMap<(Key, Key), Noun>,
+    atoms: HashMap<Atom, Atom>,
+}
+
+impl Arena {
+    /// new constructs an empty Arena.
+    pub fn new() -> Self {
+        Arena {
+            id: NEXT_ARENA.fetch_add(1, Ordering::SeqCst),
+            cells: HashMap::new(),
+            atoms: HashMap::new(),
+        }
+    }
+
+    /// len returns the number of distinct cells in the Arena.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn key(&self, noun: &Noun) -> Key {
+        match noun {
+            &Noun::Atom(ref a) => Key::Atom(a.clone()),
+            &Noun::Cell(ref cell) => {
+                // Only ever called on nouns this Arena interned.
+                Key::Cell(cell.interned().expect("Cell was not interned").index)
+            }
+        }
+    }
+
+    fn intern_atom(&mut self, a: &Atom) -> Atom {
+        if let &Atom::Direct(_) = a {
+            return a.clone();
+        }
+        // Share the digits of equal indirect atoms.
+        self.atoms.entry(a.clone()).or_insert_with(|| a.clone()).clone()
+    }
+
+    /// cons constructs the interned cell of an interned head and tail.
+    pub fn cons(&mut self, head: Noun, tail: Noun) -> Noun {
+        let head = self.intern(&head);
+        let tail = self.intern(&tail);
+        self.join(head, tail)
+    }
+
+    // join returns the interned cell of a head and tail this Arena already
+    // interned.
+    fn join(&mut self, head: Noun, tail: Noun) -> Noun {
+        let key = (self.key(&head), self.key(&tail));
+        if let Some(noun) = self.cells.get(&key) {
+            return noun.clone();
+        }
+        let id = InternId {
+            arena: self.id,
+            index: self.cells.len(),
+        };
+        let noun = Noun::cons_interned(head, tail, id);
+        self.cells.insert(key, noun.clone());
+        noun
+    }
+
+    /// intern returns the canonical copy of a noun in this Arena.
+    pub fn intern(&mut self, noun: &Noun) -> Noun {
+        // Intern bottom up with an explicit stack so deep nouns can't
+        // overflow the native stack. Interned heads and tails wait on done
+        // for the cell that joins them.
+        let mut stack = vec![(noun.clone(), false)];
+        let mut done = Vec::new();
+        while let Some((noun, ready)) = stack.pop() {
+            let interned = match noun {
+                Noun::Atom(ref a) => Noun::Atom(self.intern_atom(a)),
+                Noun::Cell(ref cell) => {
+                    if cell.interned().map_or(false, |id| id.arena == self.id) {
+                        noun.clone()
+                    } else if ready {
+                        let tail = done.pop().expect("Interned tail is missing");
+                        let head = done.pop().expect("Interned head is missing");
+                        self.join(head, tail)
+                    } else {
+                        stack.push((noun.clone(), true));
+                        stack.push((cell.tail().clone(), false));
+                        stack.push((cell.head().clone(), false));
+                        continue;
+                    }
+                }
+            };
+            done.push(interned);
+        }
+        done.pop().expect("Interned noun is missing")
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use std::rc::Rc;
+    use std::str::FromStr;
+    use arena::Arena;
+    use atom::Atom;
+    use parser::{Noun, atom};
+
+    fn same_cell(a: &Noun, b: &Noun) -> bool {
+        match (a, b) {
+            (&Noun::Cell(ref a), &Noun::Cell(ref b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_intern_shares_equal_nouns() {
+        let mut arena = Arena::new();
+        let a = arena.intern(&cell!(atom(1), cell!(atom(2), atom(3)), atom(4)));
+        let b = arena.intern(&cell!(atom(1), cell!(atom(2), atom(3)), atom(4)));
+        assert!(a.is_interned());
+        assert!(same_cell(&a, &b));
+        assert_eq!(a, b);
+        // [1 [2 3] 4] [[2 3] 4] [2 3]
+        assert_eq!(arena.len(), 3);
+        let c = arena.intern(&cell!(atom(1), cell!(atom(2), atom(3)), atom(5)));
+        assert!(a != c);
+        assert!(!cell!(atom(1), atom(2)).is_interned());
+    }
+
+    #[test]
+    fn test_intern_is_idempotent() {
+        let mut arena = Arena::new();
+        let a = arena.intern(&cell!(atom(1), atom(2)));
+        assert!(same_cell(&a, &arena.intern(&a)));
+        assert_eq!(arena.len(), 1);
+    }
+
+    #[test]
+    fn test_interned_equals_uninterned() {
+        let mut arena = Arena::new();
+        let mut other = Arena::new();
+        let plain = cell!(atom(1), atom(2));
+        let a = arena.intern(&plain);
+        let b = other.intern(&plain);
+        assert_eq!(a, plain);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_intern_deep_noun() {
+        let mut list = atom(0);
+        for i in 0..100000 {
+            list = Noun::cons(atom(i), list);
+        }
+        let mut arena = Arena::new();
+        let interned = arena.intern(&list);
+        assert!(interned.is_interned());
+        assert_eq!(arena.len(), 100000);
+        assert_eq!(interned, list);
+    }
+
+    #[test]
+    fn test_intern_shares_indirect_atoms() {
+        let mut arena = Arena::new();
+        let big = Atom::from_str("18446744073709551616").unwrap();
+        let a = arena.intern(&cell!(Noun::Atom(big.clone()), atom(1)));
+        let b = arena.intern(&cell!(atom(2), Noun::Atom(big.clone())));
+        match (a.head().unwrap(), b.tail().unwrap()) {
+            (&Noun::Atom(Atom::Indirect(ref x)), &Noun::Atom(Atom::Indirect(ref y))) => {
+                assert!(Rc::ptr_eq(x, y));
+            }
+            _ => panic!("Expected indirect atoms"),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! atom implements arbitrary precision nock atoms.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use num_bigint::{BigUint, ParseBigIntError};
+use num_traits::{One, ToPrimitive};
+
+/// An Atom is an unbounded natural number.
+///
+/// Atoms that fit in a u64 are stored directly so the common case never
+/// touches the bignum code. Anything larger is stored as a BigUint. An Atom
+/// is always normalized so an Indirect atom never holds a value that would
+/// fit in a Direct one. This keeps the derived equality correct.
+///
+/// Indirect atoms are reference counted so cloning an Atom never copies
+/// its digits.
+#[derive(Debug,PartialEq,Eq,Clone,Hash)]
+pub enum Atom {
+    Direct(u64),
+    Indirect(Rc<BigUint>),
+}
+
+impl Atom {
+    /// from_biguint constructs a normalized Atom from a BigUint.
+    pub fn from_biguint(n: BigUint) -> Atom {
+        match n.to_u64() {
+            Some(u) => Atom::Direct(u),
+            None => Atom::Indirect(Rc::new(n)),
+        }
+    }
+
+    /// from_bytes_le constructs an Atom from LSB first bytes.
+    pub fn from_bytes_le(bytes: &[u8]) -> Atom {
+        if bytes.len() <= 8 {
+            let mut u = 0u64;
+            for (i, b) in bytes.iter().enumerate() {
+                u |= (*b as u64) << (8 * i);
+            }
+            return Atom::Direct(u);
+        }
+        Atom::from_biguint(BigUint::from_bytes_le(bytes))
+    }
+
+    /// to_bytes_le returns the LSB first bytes of the Atom with no trailing
+    /// zero bytes. The Atom 0 has no bytes.
+    pub fn to_bytes_le(&self) -> Vec<u8> {
+        match self {
+            &Atom::Direct(u) => {
+                let mut bytes = Vec::new();
+                let mut next = u;
+                while next > 0 {
+                    bytes.push((next & 0xff) as u8);
+                    next = next >> 8;
+                }
+                bytes
+            }
+            &Atom::Indirect(ref n) => n.to_bytes_le(),
+        }
+    }
+
+    /// to_biguint returns the Atom as a BigUint.
+    pub fn to_biguint(&self) -> BigUint {
+        match self {
+            &Atom::Direct(u) => BigUint::from(u),
+            &Atom::Indirect(ref n) => n.as_ref().clone(),
+        }
+    }
+
+    /// as_u64 returns the Atom as a u64 if it fits in one.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            &Atom::Direct(u) => Some(u),
+            &Atom::Indirect(_) => None,
+        }
+    }
+
+    /// bit_len returns the number of significant bits in the Atom.
+    pub fn bit_len(&self) -> usize {
+        match self {
+            &Atom::Direct(u) => 64 - u.leading_zeros() as usize,
+            &Atom::Indirect(ref n) => n.bits(),
+        }
+    }
+
+    /// inc returns the Atom incremented by 1.
+    pub fn inc(&self) -> Atom {
+        match self {
+            &Atom::Direct(u) => {
+                match u.checked_add(1) {
+                    Some(u) => Atom::Direct(u),
+                    None => Atom::Indirect(Rc::new(BigUint::from(u) + BigUint::one())),
+                }
+            }
+            &Atom::Indirect(ref n) => Atom::Indirect(Rc::new(n.as_ref() + BigUint::one())),
+        }
+    }
+}
+
+impl From<u64> for Atom {
+    fn from(u: u64) -> Self {
+        Atom::Direct(u)
+    }
+}
+
+impl From<BigUint> for Atom {
+    fn from(n: BigUint) -> Self {
+        Atom::from_biguint(n)
+    }
+}
+
+impl FromStr for Atom {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(u) = u64::from_str(s) {
+            return Ok(Atom::Direct(u));
+        }
+        Ok(Atom::from_biguint(try!(BigUint::from_str(s))))
+    }
+}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Atom) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Atom {
+    fn cmp(&self, other: &Atom) -> Ordering {
+        // Normalization means every Indirect atom is larger than every
+        // Direct atom.
+        match (self, other) {
+            (&Atom::Direct(a), &Atom::Direct(b)) => a.cmp(&b),
+            (&Atom::Direct(_), &Atom::Indirect(_)) => Ordering::Less,
+            (&Atom::Indirect(_), &Atom::Direct(_)) => Ordering::Greater,
+            (&Atom::Indirect(ref a), &Atom::Indirect(ref b)) => a.cmp(b),
+        }
+    }
+}
+
+impl Display for Atom {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            &Atom::Direct(u) => write!(fmt, "{}", u),
+            &Atom::Indirect(ref n) => write!(fmt, "{}", n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod atom_tests {
+    use std::str::FromStr;
+    use std::u64;
+    use atom::Atom;
+
+    #[test]
+    fn test_parse_normalizes() {
+        assert_eq!(Atom::from_str("42").unwrap(), Atom::Direct(42));
+        assert_eq!(Atom::from_str("18446744073709551615").unwrap(),
+                   Atom::Direct(u64::MAX));
+        let big = Atom::from_str("18446744073709551616").unwrap();
+        if let Atom::Direct(_) = big {
+            panic!("2^64 should not be a direct atom");
+        }
+        assert_eq!(format!("{}", big), "18446744073709551616");
+    }
+
+    #[test]
+    fn test_inc_overflows_into_bignum() {
+        let max = Atom::Direct(u64::MAX);
+        assert_eq!(max.inc(), Atom::from_str("18446744073709551616").unwrap());
+        assert_eq!(max.inc().bit_len(), 65);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        assert_eq!(Atom::Direct(0).to_bytes_le(), Vec::<u8>::new());
+        assert_eq!(Atom::Direct(0x1234).to_bytes_le(), vec![0x34, 0x12]);
+        let big = Atom::from_str("340282366920938463463374607431768211455").unwrap();
+        assert_eq!(big.to_bytes_le(), vec![0xff; 16]);
+        assert_eq!(Atom::from_bytes_le(&big.to_bytes_le()), big);
+        assert_eq!(Atom::from_bytes_le(&[0x34, 0x12]), Atom::Direct(0x1234));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let big = Atom::from_str("18446744073709551616").unwrap();
+        assert!(Atom::Direct(u64::MAX) < big);
+        assert!(Atom::Direct(1) < Atom::Direct(2));
+        assert!(big.inc() > big);
+    }
+}
@@ -0,0 +1,481 @@
+//! aura implements rendering atoms in Hoon auras.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str;
+use std::str::FromStr;
+
+use atom::Atom;
+use mug::murmur3_32;
+
+/// Aura selects how an atom is rendered.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Aura {
+    /// @ud unsigned decimal with dots every 3 digits: 1.234.567
+    Ud,
+    /// @ux unsigned hexadecimal with dots every 4 digits: 0x12.abcd
+    Ux,
+    /// @ub unsigned binary with dots every 4 digits: 0b10.0110
+    Ub,
+    /// @t a UTF-8 text cord: 'hello'
+    T,
+    /// @tas a symbol: %hello
+    Tas,
+    /// @p a ship name: ~sampel-palnet
+    P,
+    /// @da an absolute date: ~2017.5.14..20.15.46..1234
+    Da,
+    /// Guess the aura from the atom's content.
+    Auto,
+}
+
+impl FromStr for Aura {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = if s.starts_with('@') { &s[1..] } else { s };
+        match name {
+            "ud" => Ok(Aura::Ud),
+            "ux" => Ok(Aura::Ux),
+            "ub" => Ok(Aura::Ub),
+            "t" => Ok(Aura::T),
+            "tas" => Ok(Aura::Tas),
+            "p" => Ok(Aura::P),
+            "da" => Ok(Aura::Da),
+            "auto" => Ok(Aura::Auto),
+            _ => Err(format!("Unknown aura {}. Expected one of @ud @ux @ub @t @tas @p @da auto", s)),
+        }
+    }
+}
+
+impl Display for Aura {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
+        let name = match self {
+            &Aura::Ud => "@ud",
+            &Aura::Ux => "@ux",
+            &Aura::Ub => "@ub",
+            &Aura::T => "@t",
+            &Aura::Tas => "@tas",
+            &Aura::P => "@p",
+            &Aura::Da => "@da",
+            &Aura::Auto => "auto",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+impl Aura {
+    /// render renders the atom in this aura.
+    ///
+    /// An atom that isn't valid in the aura, like bytes that aren't a
+    /// symbol for @tas or more than 128 bits for @da, falls back to @ux.
+    pub fn render(&self, a: &Atom) -> String {
+        match self {
+            &Aura::Ud => render_ud(a),
+            &Aura::Ux => render_radix(a, 4, "0x"),
+            &Aura::Ub => render_radix(a, 1, "0b"),
+            &Aura::T => render_t(a),
+            &Aura::Tas => render_tas(a).unwrap_or_else(|| render_radix(a, 4, "0x")),
+            &Aura::P => render_p(a),
+            &Aura::Da => render_da(a).unwrap_or_else(|| render_radix(a, 4, "0x")),
+            &Aura::Auto => guess(a).render(a),
+        }
+    }
+}
+
+// dot_groups inserts a dot between every group of size digits counting from
+// the right.
+fn dot_groups(digits: &str, size: usize) -> String {
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % size == 0 {
+            out.push('.');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn render_ud(a: &Atom) -> String {
+    dot_groups(&a.to_string(), 3)
+}
+
+// render_radix renders the atom in base 2^bits with a dot every 4 digits.
+fn render_radix(a: &Atom, bits: usize, prefix: &str) -> String {
+    let bytes = a.to_bytes_le();
+    let mut digits = String::new();
+    for b in bytes.iter().rev() {
+        if bits == 4 {
+            digits.push_str(&format!("{:02x}", b));
+        } else {
+            digits.push_str(&format!("{:08b}", b));
+        }
+    }
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return format!("{}0", prefix);
+    }
+    format!("{}{}", prefix, dot_groups(digits, 4))
+}
+
+fn render_t(a: &Atom) -> String {
+    let bytes = a.to_bytes_le();
+    let mut out = String::from("'");
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        // Print the longest valid UTF-8 prefix and escape the first byte
+        // that isn't.
+        let valid = match str::from_utf8(rest) {
+            Ok(s) => s,
+            Err(e) => str::from_utf8(&rest[..e.valid_up_to()]).unwrap(),
+        };
+        for c in valid.chars() {
+            match c {
+                '\'' => out.push_str("\\'"),
+                '\\' => out.push_str("\\\\"),
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        out.push_str(&format!("\\{:02x}", b));
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        rest = &rest[valid.len()..];
+        if !rest.is_empty() {
+            out.push_str(&format!("\\{:02x}", rest[0]));
+            rest = &rest[1..];
+        }
+    }
+    out.push('\'');
+    out
+}
+
+fn render_tas(a: &Atom) -> Option<String> {
+    let bytes = a.to_bytes_le();
+    if bytes.is_empty() {
+        return Some("%$".to_string());
+    }
+    let first = bytes[0];
+    if !(first >= b'a' && first <= b'z') {
+        return None;
+    }
+    for b in &bytes[1..] {
+        let b = *b;
+        if !((b >= b'a' && b <= b'z') || (b >= b'0' && b <= b'9') || b == b'-') {
+            return None;
+        }
+    }
+    Some(format!("%{}", str::from_utf8(&bytes).unwrap()))
+}
+
+// The 256 three letter prefix syllables of @p, 16 per row.
+const PREFIXES: [&'static str; 16] = [
+    "dozmarbinwansamlitsighidfidlissogdirwacsabwissib",
+    "rigsoldopmodfoglidhopdardorlorhodfolrintogsilmir",
+    "holpaslacrovlivdalsatlibtabhanticpidtorbolfosdot",
+    "losdilforpilramtirwintadbicdifrocwidbisdasmidlop",
+    "rilnardapmolsanlocnovsitnidtipsicropwitnatpanmin",
+    "ritpodmottamtolsavposnapnopsomfinfonbanmorworsip",
+    "ronnorbotwicsocwatdolmagpicdavbidbaltimtasmallig",
+    "sivtagpadsaldivdactansidfabtarmonranniswolmispal",
+    "lasdismaprabtobrollatlonnodnavfignomnibpagsopral",
+    "bilhaddocridmocpacravripfaltodtiltinhapmicfanpat",
+    "taclabmogsimsonpinlomrictapfirhasbosbatpochactid",
+    "havsaplindibhosdabbitbarracparloddosbortochilmac",
+    "tomdigfilfasmithobharmighinradmashalraglagfadtop",
+    "mophabnilnosmilfopfamdatnoldinhatnacrisfotribhoc",
+    "nimlarfitwalrapsarnalmoslandondanladdovrivbacpol",
+    "laptalpitnambonrostonfodponsovnocsorlavmatmipfip",
+];
+
+// The 256 three letter suffix syllables of @p, 16 per row.
+const SUFFIXES: [&'static str; 16] = [
+    "zodnecbudwessevpersutletfulpensytdurwepserwylsun",
+    "rypsyxdyrnuphebpeglupdepdysputlughecryttyvsydnex",
+    "lunmeplutseppesdelsulpedtemledtulmetwenbynhexfeb",
+    "pyldulhetmevruttylwydtepbesdexsefwycburderneppur",
+    "rysrebdennutsubpetrulsynregtydsupsemwynrecmegnet",
+    "secmulnymtevwebsummutnyxrextebfushepbenmuswyxsym",
+    "selrucdecwexsyrwetdylmynmesdetbetbeltuxtugmyrpel",
+    "syptermebsetdutdegtexsurfeltudnuxruxrenwytnubmed",
+    "lytdusnebrumtynseglyxpunresredfunrevrefmectedrus",
+    "bexlebduxrynnumpyxrygryxfeptyrtustyclegnemfermer",
+    "tenlusnussyltecmexpubrymtucfyllepdebbermughuttun",
+    "bylsudpemdevlurdefbusbeprunmelpexdytbyttyplevmyl",
+    "wedducfurfexnulluclennerlexrupnedlecrydlydfenwel",
+    "nydhusrelrudneshesfetdesretdunlernyrsebhulryllud",
+    "remlysfynwerrycsugnysnyllyndyndemluxfedsedbecmun",
+    "lyrtesmudnytbyrsenwegfyrmurtelreptegpecnelnevfes",
+];
+
+fn syllable(table: &[&'static str; 16], i: u8) -> &'static str {
+    let row = table[(i / 16) as usize];
+    let col = (i % 16) as usize * 3;
+    &row[col..col + 3]
+}
+
+// The round keys of the @p Feistel cipher.
+const RAKU: [u32; 4] = [0xb76d5eed, 0xee281300, 0x85bcae01, 0x4b387af7];
+
+fn eff(j: usize, r: u64) -> u64 {
+    let bytes = [(r & 0xff) as u8, ((r >> 8) & 0xff) as u8];
+    murmur3_32(&bytes, RAKU[j]) as u64
+}
+
+// fe is the generalized Feistel cipher from hoon's ob core.
+fn fe(r: usize, a: u64, b: u64, m: u64) -> u64 {
+    let mut j = 1;
+    let mut ell = m % a;
+    let mut arr = m / a;
+    loop {
+        if j > r {
+            if r % 2 != 0 || arr == a {
+                return arr * a + ell;
+            }
+            return ell * a + arr;
+        }
+        let f = eff(j - 1, arr);
+        let tmp = if j % 2 != 0 { (f + ell) % a } else { (f + ell) % b };
+        j += 1;
+        ell = arr;
+        arr = tmp;
+    }
+}
+
+// fee cycle walks fe so the result stays in the domain.
+fn fee(m: u64) -> u64 {
+    let (r, a, b) = (4, 0xffff, 0x10000);
+    let k = 0xffff * 0x10000;
+    let c = fe(r, a, b, m);
+    if c < k {
+        return c;
+    }
+    fe(r, a, b, c)
+}
+
+// fein scrambles planets and moons so consecutive ships don't get similar
+// names. Galaxies, stars and comets are left alone.
+fn fein(pyn: u64) -> u64 {
+    if pyn >= 0x10000 && pyn <= 0xffffffff {
+        return 0x10000 + fee(pyn - 0x10000);
+    }
+    if pyn > 0xffffffff {
+        return (pyn & 0xffffffff00000000) | fein(pyn & 0xffffffff);
+    }
+    pyn
+}
+
+fn render_p(a: &Atom) -> String {
+    let bytes = match a.as_u64() {
+        Some(u) => Atom::Direct(fein(u)).to_bytes_le(),
+        None => a.to_bytes_le(),
+    };
+    if bytes.len() <= 1 {
+        return format!("~{}", syllable(&SUFFIXES, bytes.get(0).cloned().unwrap_or(0)));
+    }
+    // One prefix and suffix pair per 16 bit word with the most significant
+    // word first. Every 4 words are separated by -- instead of -.
+    let words = (bytes.len() + 1) / 2;
+    let mut out = String::from("~");
+    for imp in (0..words).rev() {
+        let lo = bytes[imp * 2];
+        let hi = bytes.get(imp * 2 + 1).cloned().unwrap_or(0);
+        out.push_str(syllable(&PREFIXES, hi));
+        out.push_str(syllable(&SUFFIXES, lo));
+        if imp > 0 {
+            out.push_str(if imp % 4 == 0 { "--" } else { "-" });
+        }
+    }
+    out
+}
+
+// The @da of ~1970.1.1 is this many seconds shifted left by 64.
+const UNIX_EPOCH: i128 = 0x8000000cce9e0d80;
+
+// civil_from_days converts days since 1970-01-01 to a proleptic Gregorian
+// (year, month, day) using astronomical year numbering.
+fn civil_from_days(days: i128) -> (i128, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+fn render_da(a: &Atom) -> Option<String> {
+    let bytes = a.to_bytes_le();
+    if bytes.len() > 16 {
+        return None;
+    }
+    let mut frac = 0u64;
+    let mut secs = 0u64;
+    for (i, b) in bytes.iter().enumerate() {
+        if i < 8 {
+            frac |= (*b as u64) << (8 * i);
+        } else {
+            secs |= (*b as u64) << (8 * (i - 8));
+        }
+    }
+    let unix = secs as i128 - UNIX_EPOCH;
+    let days = if unix >= 0 { unix / 86400 } else { (unix - 86399) / 86400 };
+    let sod = unix - days * 86400;
+    let (year, month, day) = civil_from_days(days);
+    // There is no year 0. The year before ~1.1.1 is ~1-.12.31.
+    let mut out = if year > 0 {
+        format!("~{}.{}.{}", year, month, day)
+    } else {
+        format!("~{}-.{}.{}", 1 - year, month, day)
+    };
+    if sod == 0 && frac == 0 {
+        return Some(out);
+    }
+    out.push_str(&format!("..{:02}.{:02}.{:02}", sod / 3600, sod % 3600 / 60, sod % 60));
+    if frac != 0 {
+        let mut groups: Vec<u64> = (0..4).map(|i| (frac >> (48 - 16 * i)) & 0xffff).collect();
+        while groups.last() == Some(&0) {
+            groups.pop();
+        }
+        let groups: Vec<String> = groups.iter().map(|g| format!("{:04x}", g)).collect();
+        out.push_str(&format!("..{}", groups.join(".")));
+    }
+    Some(out)
+}
+
+/// guess picks a likely aura for an atom.
+///
+/// Small numbers are @ud. Atoms made entirely of printable text are @tas if
+/// they are a valid symbol and @t otherwise. 128 bit atoms that fall
+/// between the years 1800 and 2300 are @da. Anything else that fits in 32
+/// bits is @ud and the rest is @ux.
+pub fn guess(a: &Atom) -> Aura {
+    if let Some(u) = a.as_u64() {
+        if u < 0x10000 {
+            return Aura::Ud;
+        }
+    }
+    let bytes = a.to_bytes_le();
+    if bytes.len() >= 2 && bytes.iter().all(|b| *b >= 0x20 && *b < 0x7f) {
+        if render_tas(a).is_some() {
+            return Aura::Tas;
+        }
+        return Aura::T;
+    }
+    if bytes.len() > 8 && bytes.len() <= 16 {
+        // ~1800.1.1 and ~2300.1.1
+        let secs = Atom::from_bytes_le(&bytes[8..]).as_u64().unwrap() as i128 - UNIX_EPOCH;
+        if secs >= -5364662400 && secs < 10413792000 {
+            return Aura::Da;
+        }
+    }
+    if bytes.len() <= 4 {
+        return Aura::Ud;
+    }
+    Aura::Ux
+}
+
+#[cfg(test)]
+mod aura_tests {
+    use std::str::FromStr;
+    use atom::Atom;
+    use aura::{Aura, UNIX_EPOCH};
+
+    fn da(secs: i128, frac: u64) -> Atom {
+        let secs = (UNIX_EPOCH + secs) as u64;
+        let mut bytes = Vec::new();
+        for i in 0..8 {
+            bytes.push(((frac >> (8 * i)) & 0xff) as u8);
+        }
+        for i in 0..8 {
+            bytes.push(((secs >> (8 * i)) & 0xff) as u8);
+        }
+        Atom::from_bytes_le(&bytes)
+    }
+
+    #[test]
+    fn test_numeric_auras() {
+        assert_eq!(Aura::Ud.render(&Atom::Direct(0)), "0");
+        assert_eq!(Aura::Ud.render(&Atom::Direct(1234567)), "1.234.567");
+        assert_eq!(Aura::Ud.render(&Atom::Direct(123)), "123");
+        assert_eq!(Aura::Ux.render(&Atom::Direct(0)), "0x0");
+        assert_eq!(Aura::Ux.render(&Atom::Direct(0x12abcd)), "0x12.abcd");
+        assert_eq!(Aura::Ub.render(&Atom::Direct(0b100110)), "0b10.0110");
+        let big = Atom::from_str("340282366920938463463374607431768211456").unwrap();
+        assert_eq!(Aura::Ux.render(&big), "0x1.0000.0000.0000.0000.0000.0000.0000.0000");
+    }
+
+    #[test]
+    fn test_text_auras() {
+        let hello = Atom::from_bytes_le(b"hello");
+        assert_eq!(Aura::T.render(&hello), "'hello'");
+        assert_eq!(Aura::Tas.render(&hello), "%hello");
+        assert_eq!(Aura::T.render(&Atom::from_bytes_le(b"it's\n")), "'it\\'s\\0a'");
+        assert_eq!(Aura::T.render(&Atom::from_bytes_le("caf\u{e9}".as_bytes())), "'caf\u{e9}'");
+        assert_eq!(Aura::T.render(&Atom::from_bytes_le(&[0x61, 0xff])), "'a\\ff'");
+        assert_eq!(Aura::Tas.render(&Atom::Direct(0)), "%$");
+        assert_eq!(Aura::Tas.render(&Atom::from_bytes_le(b"Hi")), "0x6948");
+    }
+
+    #[test]
+    fn test_ship_names() {
+        let cases = vec![(0, "~zod"),
+                         (1, "~nec"),
+                         (255, "~fes"),
+                         (256, "~marzod"),
+                         (65535, "~fipfes"),
+                         (65536, "~dapnep-ronmyl"),
+                         (1624961343, "~sampel-palnet"),
+                         (4294967296, "~doznec-dozzod-dozzod")];
+        for (n, name) in cases {
+            assert_eq!(Aura::P.render(&Atom::Direct(n)), name);
+        }
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(Aura::Da.render(&da(0, 0)), "~1970.1.1");
+        // 2017-05-14T20:15:46Z
+        assert_eq!(Aura::Da.render(&da(1494792946, 0)), "~2017.5.14..20.15.46");
+        assert_eq!(Aura::Da.render(&da(1494792946, 0x1234 << 48)),
+                   "~2017.5.14..20.15.46..1234");
+        assert_eq!(Aura::Da.render(&da(951782400, 0)), "~2000.2.29");
+        // The day before ~1.1.1
+        assert_eq!(Aura::Da.render(&da(-62135683200, 0)), "~1-.12.31");
+    }
+
+    #[test]
+    fn test_guess() {
+        assert_eq!(Aura::Auto.render(&Atom::Direct(42)), "42");
+        assert_eq!(Aura::Auto.render(&Atom::from_bytes_le(b"hello")), "%hello");
+        assert_eq!(Aura::Auto.render(&Atom::from_bytes_le(b"Hello!")), "'Hello!'");
+        assert_eq!(Aura::Auto.render(&da(0, 0)), "~1970.1.1");
+        assert_eq!(Aura::Auto.render(&Atom::Direct(0xdeadbeefcafe)), "0xdead.beef.cafe");
+        assert_eq!(Aura::Auto.render(&Atom::Direct(0x8000_0001)), "2.147.483.649");
+    }
+
+    #[test]
+    fn test_parse_aura() {
+        assert_eq!(Aura::from_str("@ux").unwrap(), Aura::Ux);
+        assert_eq!(Aura::from_str("da").unwrap(), Aura::Da);
+        assert_eq!(Aura::from_str("auto").unwrap(), Aura::Auto);
+        assert!(Aura::from_str("@zz").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! bail classifies the ways a nock computation can crash.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fmt;
+
+use atom::Atom;
+use parser::Noun;
+use pretty::Printer;
+
+/// Bail is the reason a computation crashed. The names follow the bail
+/// kinds of the Urbit runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bail {
+    /// The formula can never be computed against the subject.
+    Exit(Exit),
+    /// A crash that might not happen again like a malformed input.
+    Fail(String),
+    /// The computation ran out of stack with frames pending.
+    Oom { frames: usize },
+    /// The computation was interrupted after steps steps.
+    Intr { steps: u64 },
+    /// The computation ran out of fuel after steps steps.
+    Fuel { steps: u64 },
+    /// Nock 12 read a path the namespace didn't answer.
+    Blocked { reference: Noun, path: Noun },
+    /// The interpreter was used wrong or is wrong.
+    Bug(String),
+}
+
+/// Exit is a deterministic crash along with the nouns that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exit {
+    /// /[axis noun] doesn't exist.
+    Slot { axis: Atom, noun: Noun },
+    /// #[axis value noun] doesn't exist.
+    Edit { axis: Atom, noun: Noun },
+    /// +noun of a cell.
+    Increment(Noun),
+    /// =noun of an atom.
+    Compare(Noun),
+    /// A 6 test that's not 0 or 1.
+    Loobean(Noun),
+    /// A 9 core without an arm at axis.
+    Arm { axis: Atom, core: Noun },
+    /// A formula whose opcode isn't an instruction.
+    Opcode { opcode: Noun, formula: Noun },
+    /// A formula that's missing the arguments of its opcode.
+    Formula { opcode: Option<u64>, formula: Noun },
+}
+
+impl Bail {
+    /// name returns the Urbit name of the bail.
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Bail::Exit(_) => "exit",
+            &Bail::Fail(_) => "fail",
+            &Bail::Oom { .. } => "oom",
+            &Bail::Intr { .. } => "intr",
+            &Bail::Fuel { .. } => "fuel",
+            &Bail::Blocked { .. } => "blocked",
+            &Bail::Bug(_) => "bug",
+        }
+    }
+
+    /// exit_code returns the process exit code the command line uses for
+    /// the bail.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            &Bail::Exit(_) => 2,
+            &Bail::Fail(_) => 3,
+            &Bail::Oom { .. } => 4,
+            &Bail::Fuel { .. } => 5,
+            &Bail::Blocked { .. } => 6,
+            &Bail::Bug(_) => 70,
+            // The shell's convention for SIGINT.
+            &Bail::Intr { .. } => 130,
+        }
+    }
+}
+
+// brief renders a noun that may be huge without its deep cells.
+fn brief(noun: &Noun) -> String {
+    Printer::new(1 << 16, Some(3)).render(noun)
+}
+
+impl fmt::Display for Bail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Bail::Exit(ref exit) => write!(f, "{}", exit),
+            &Bail::Fail(ref msg) => write!(f, "{}", msg),
+            &Bail::Oom { frames } => write!(f, "!! Stack overflow at {} frames", frames),
+            &Bail::Intr { steps } => write!(f, "!! Interrupted after {} steps", steps),
+            &Bail::Fuel { steps } => write!(f, "!! Out of fuel after {} steps", steps),
+            &Bail::Blocked { ref path, .. } => write!(f, "!! Blocked on scry of {}", brief(path)),
+            &Bail::Bug(ref msg) => write!(f, "!! Bug: {}", msg),
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Exit::Slot { ref axis, ref noun } => {
+                write!(f, "!! Can't take axis {} of {}", axis, brief(noun))
+            }
+            &Exit::Edit { ref axis, ref noun } => {
+                write!(f, "!! Can't edit axis {} of {}", axis, brief(noun))
+            }
+            &Exit::Increment(ref noun) => write!(f, "!! Can't increment the cell {}", brief(noun)),
+            &Exit::Compare(ref noun) => {
+                write!(f, "!! Can't compare the atom {} like a cell", brief(noun))
+            }
+            &Exit::Loobean(ref noun) => {
+                write!(f, "!! Nock 6 expected a loobean test not {}", brief(noun))
+            }
+            &Exit::Arm { ref axis, ref core } => {
+                write!(f, "!! Nock 9 can't find arm {} of the core {}", axis, brief(core))
+            }
+            &Exit::Opcode { ref opcode, .. } => {
+                write!(f, "!! Unknown Nock instruction {}", brief(opcode))
+            }
+            &Exit::Formula { opcode: Some(op), ref formula } => {
+                write!(f, "!! Malformed Nock {} formula {}", op, brief(formula))
+            }
+            &Exit::Formula { opcode: None, ref formula } => {
+                write!(f, "!! Malformed Nock formula {}", brief(formula))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod bail_tests {
+    use atom::Atom;
+    use bail::{Bail, Exit};
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_display() {
+        let deep = cell!(cell!(cell!(cell!(atom(1), atom(2)), atom(3)), atom(4)), atom(5));
+        let exit = Exit::Slot {
+            axis: Atom::Direct(2),
+            noun: deep,
+        };
+        assert_eq!(exit.to_string(), "!! Can't take axis 2 of [[[... 3] 4] 5]");
+        assert_eq!(Bail::Exit(exit).name(), "exit");
+        let formula = Exit::Formula {
+            opcode: Some(5),
+            formula: cell!(atom(5), atom(1)),
+        };
+        assert_eq!(formula.to_string(), "!! Malformed Nock 5 formula [5 1]");
+        assert_eq!(Exit::Compare(atom(7)).to_string(), "!! Can't compare the atom 7 like a cell");
+        assert_eq!(Bail::Fuel { steps: 10 }.to_string(), "!! Out of fuel after 10 steps");
+        assert_eq!(Bail::Intr { steps: 10 }.exit_code(), 130);
+    }
+}
@@ -0,0 +1,371 @@
+//! convert implements conversions between Rust values and nouns.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//!
+//! The conventions follow Hoon's:
+//!
+//! * Integers are atoms.
+//! * Byte strings are cords, atoms whose bytes are read LSB first.
+//! * bool is a loobean, 0 for true and 1 for false.
+//! * Tuples are cells, (a, b, c) is [a b c].
+//! * Vec<T> is a null terminated list, vec![1, 2] is [1 2 0].
+//! * Option<T> is a unit, None is 0 and Some(x) is [0 x]. Converting back
+//!   goes through Unit<T>.
+use std::convert::{Infallible, TryFrom};
+use std::error;
+use std::fmt;
+use std::fmt::Display;
+
+use num_bigint::BigUint;
+
+use atom::Atom;
+use parser::{Noun, atom};
+
+make_error!(ConvertError, "ConvertError: {}\n");
+
+impl From<Infallible> for ConvertError {
+    fn from(err: Infallible) -> Self {
+        match err {}
+    }
+}
+
+/// Cord is a byte string stored in a noun as an LSB first atom.
+///
+/// Trailing zero bytes can't be represented in an atom so they don't
+/// survive a round trip.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Cord(pub Vec<u8>);
+
+macro_rules! integer_conversions {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Noun {
+                fn from(n: $t) -> Noun {
+                    atom(n as u64)
+                }
+            }
+
+            impl TryFrom<Noun> for $t {
+                type Error = ConvertError;
+
+                fn try_from(noun: Noun) -> Result<$t, ConvertError> {
+                    let n = try!(u64::try_from(noun));
+                    if n > (<$t>::max_value() as u64) {
+                        return Err(ConvertError::new(format!("!! {} is too large for {}",
+                                                             n,
+                                                             stringify!($t))));
+                    }
+                    Ok(n as $t)
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(u8, u16, u32, usize);
+
+impl From<u64> for Noun {
+    fn from(n: u64) -> Noun {
+        atom(n)
+    }
+}
+
+impl TryFrom<Noun> for u64 {
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<u64, ConvertError> {
+        match noun {
+            Noun::Atom(Atom::Direct(n)) => Ok(n),
+            Noun::Atom(a) => Err(ConvertError::new(format!("!! {} is too large for u64", a))),
+            Noun::Cell(_) => Err(ConvertError::new("!! Expected an atom not a cell")),
+        }
+    }
+}
+
+impl From<Atom> for Noun {
+    fn from(a: Atom) -> Noun {
+        Noun::Atom(a)
+    }
+}
+
+impl TryFrom<Noun> for Atom {
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<Atom, ConvertError> {
+        match noun {
+            Noun::Atom(a) => Ok(a),
+            Noun::Cell(_) => Err(ConvertError::new("!! Expected an atom not a cell")),
+        }
+    }
+}
+
+impl From<BigUint> for Noun {
+    fn from(n: BigUint) -> Noun {
+        Noun::Atom(Atom::from_biguint(n))
+    }
+}
+
+impl From<bool> for Noun {
+    fn from(b: bool) -> Noun {
+        if b { atom(0) } else { atom(1) }
+    }
+}
+
+impl TryFrom<Noun> for bool {
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<bool, ConvertError> {
+        match noun {
+            Noun::Atom(Atom::Direct(0)) => Ok(true),
+            Noun::Atom(Atom::Direct(1)) => Ok(false),
+            noun => Err(ConvertError::new(format!("!! {} is not a loobean", noun))),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for Noun {
+    fn from(bytes: &'a [u8]) -> Noun {
+        Noun::Atom(Atom::from_bytes_le(bytes))
+    }
+}
+
+impl<'a> From<&'a str> for Noun {
+    fn from(s: &'a str) -> Noun {
+        Noun::from(s.as_bytes())
+    }
+}
+
+impl From<String> for Noun {
+    fn from(s: String) -> Noun {
+        Noun::from(s.as_bytes())
+    }
+}
+
+impl From<Cord> for Noun {
+    fn from(cord: Cord) -> Noun {
+        Noun::from(&cord.0[..])
+    }
+}
+
+impl TryFrom<Noun> for Cord {
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<Cord, ConvertError> {
+        Ok(Cord(try!(Atom::try_from(noun)).to_bytes_le()))
+    }
+}
+
+impl TryFrom<Noun> for String {
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<String, ConvertError> {
+        let cord = try!(Cord::try_from(noun));
+        String::from_utf8(cord.0)
+            .map_err(|e| ConvertError::new_with_cause("!! Cord is not utf-8", Box::new(e)))
+    }
+}
+
+impl<A: Into<Noun>, B: Into<Noun>> From<(A, B)> for Noun {
+    fn from(t: (A, B)) -> Noun {
+        Noun::cons(t.0.into(), t.1.into())
+    }
+}
+
+impl<A: Into<Noun>, B: Into<Noun>, C: Into<Noun>> From<(A, B, C)> for Noun {
+    fn from(t: (A, B, C)) -> Noun {
+        Noun::cons(t.0.into(), Noun::cons(t.1.into(), t.2.into()))
+    }
+}
+
+impl<A: Into<Noun>, B: Into<Noun>, C: Into<Noun>, D: Into<Noun>> From<(A, B, C, D)> for Noun {
+    fn from(t: (A, B, C, D)) -> Noun {
+        Noun::cons(t.0.into(), Noun::cons(t.1.into(), Noun::cons(t.2.into(), t.3.into())))
+    }
+}
+
+fn split(noun: Noun) -> Result<(Noun, Noun), ConvertError> {
+    match noun.as_cell() {
+        Some((head, tail)) => Ok((head.clone(), tail.clone())),
+        None => Err(ConvertError::new(format!("!! Expected a cell not {}", noun))),
+    }
+}
+
+impl<A, B> TryFrom<Noun> for (A, B)
+    where A: TryFrom<Noun>,
+          B: TryFrom<Noun>,
+          ConvertError: From<A::Error> + From<B::Error>
+{
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<(A, B), ConvertError> {
+        let (a, b) = try!(split(noun));
+        Ok((try!(A::try_from(a)), try!(B::try_from(b))))
+    }
+}
+
+impl<A, B, C> TryFrom<Noun> for (A, B, C)
+    where A: TryFrom<Noun>,
+          B: TryFrom<Noun>,
+          C: TryFrom<Noun>,
+          ConvertError: From<A::Error> + From<B::Error> + From<C::Error>
+{
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<(A, B, C), ConvertError> {
+        let (a, rest) = try!(split(noun));
+        let (b, c) = try!(split(rest));
+        Ok((try!(A::try_from(a)), try!(B::try_from(b)), try!(C::try_from(c))))
+    }
+}
+
+impl<A, B, C, D> TryFrom<Noun> for (A, B, C, D)
+    where A: TryFrom<Noun>,
+          B: TryFrom<Noun>,
+          C: TryFrom<Noun>,
+          D: TryFrom<Noun>,
+          ConvertError: From<A::Error> + From<B::Error> + From<C::Error> + From<D::Error>
+{
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<(A, B, C, D), ConvertError> {
+        let (a, rest) = try!(split(noun));
+        let (b, rest) = try!(split(rest));
+        let (c, d) = try!(split(rest));
+        Ok((try!(A::try_from(a)), try!(B::try_from(b)), try!(C::try_from(c)), try!(D::try_from(d))))
+    }
+}
+
+impl<T: Into<Noun>> From<Vec<T>> for Noun {
+    fn from(list: Vec<T>) -> Noun {
+        let mut noun = atom(0);
+        for item in list.into_iter().rev() {
+            noun = Noun::cons(item.into(), noun);
+        }
+        noun
+    }
+}
+
+impl<T> TryFrom<Noun> for Vec<T>
+    where T: TryFrom<Noun>,
+          ConvertError: From<T::Error>
+{
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<Vec<T>, ConvertError> {
+        let mut list = Vec::new();
+        let mut next = noun;
+        loop {
+            next = match next {
+                Noun::Atom(Atom::Direct(0)) => return Ok(list),
+                Noun::Atom(a) => {
+                    return Err(ConvertError::new(format!("!! List terminated by {} not 0", a)))
+                }
+                Noun::Cell(cell) => {
+                    list.push(try!(T::try_from(cell.head().clone())));
+                    cell.tail().clone()
+                }
+            }
+        }
+    }
+}
+
+impl<T: Into<Noun>> From<Option<T>> for Noun {
+    fn from(unit: Option<T>) -> Noun {
+        match unit {
+            None => atom(0),
+            Some(x) => Noun::cons(atom(0), x.into()),
+        }
+    }
+}
+
+/// Unit converts a unit noun back into an Option.
+///
+/// std already provides TryFrom<Noun> for Option<Noun> so the reverse
+/// conversion goes through this wrapper instead.
+#[derive(Debug,PartialEq,Clone)]
+pub struct Unit<T>(pub Option<T>);
+
+impl<T> TryFrom<Noun> for Unit<T>
+    where T: TryFrom<Noun>,
+          ConvertError: From<T::Error>
+{
+    type Error = ConvertError;
+
+    fn try_from(noun: Noun) -> Result<Unit<T>, ConvertError> {
+        if noun == atom(0) {
+            return Ok(Unit(None));
+        }
+        let (tag, x) = try!(split(noun));
+        if tag != atom(0) {
+            return Err(ConvertError::new(format!("!! Unit tagged with {} not 0", tag)));
+        }
+        Ok(Unit(Some(try!(T::try_from(x)))))
+    }
+}
+
+#[cfg(test)]
+mod convert_tests {
+    use std::convert::TryFrom;
+    use convert::{Cord, Unit};
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(Noun::from(42u8), atom(42));
+        assert_eq!(Noun::from(42u64), atom(42));
+        assert_eq!(u32::try_from(atom(42)).unwrap(), 42);
+        assert!(u8::try_from(atom(256)).is_err());
+        assert!(u64::try_from(cell!(atom(1), atom(2))).is_err());
+        assert_eq!(bool::try_from(Noun::from(true)).unwrap(), true);
+        assert_eq!(Noun::from(false), atom(1));
+        assert!(bool::try_from(atom(2)).is_err());
+    }
+
+    #[test]
+    fn test_cords() {
+        // 'ab' is 0x6261.
+        assert_eq!(Noun::from("ab"), atom(0x6261));
+        assert_eq!(String::try_from(Noun::from("hello")).unwrap(), "hello");
+        assert_eq!(Cord::try_from(atom(0x6261)).unwrap(), Cord(b"ab".to_vec()));
+        assert_eq!(Noun::from(Cord(b"ab".to_vec())), atom(0x6261));
+        assert_eq!(Noun::from(""), atom(0));
+    }
+
+    #[test]
+    fn test_tuples() {
+        assert_eq!(Noun::from((1u64, 2u64)), cell!(atom(1), atom(2)));
+        assert_eq!(Noun::from((1u64, (2u64, 3u64), "a")),
+                   cell!(atom(1), cell!(atom(2), atom(3)), atom(97)));
+        let (a, b, c): (u64, u8, Noun) = TryFrom::try_from(cell!(atom(1), atom(2), atom(3), atom(4)))
+            .unwrap();
+        assert_eq!((a, b, c), (1, 2, cell!(atom(3), atom(4))));
+        assert!(<(u64, u64)>::try_from(atom(1)).is_err());
+    }
+
+    #[test]
+    fn test_lists_and_units() {
+        assert_eq!(Noun::from(vec![1u64, 2, 3]), cell!(atom(1), atom(2), atom(3), atom(0)));
+        assert_eq!(Noun::from(Vec::<u64>::new()), atom(0));
+        assert_eq!(Vec::<u64>::try_from(cell!(atom(1), atom(2), atom(0))).unwrap(), vec![1, 2]);
+        assert!(Vec::<u64>::try_from(cell!(atom(1), atom(2))).is_err());
+        assert_eq!(Noun::from(Some(5u64)), cell!(atom(0), atom(5)));
+        assert_eq!(Noun::from(None::<u64>), atom(0));
+        assert_eq!(Unit::<u64>::try_from(cell!(atom(0), atom(5))).unwrap().0, Some(5));
+        assert_eq!(Unit::<u64>::try_from(atom(0)).unwrap().0, None);
+        assert!(Unit::<u64>::try_from(cell!(atom(1), atom(5))).is_err());
+        let subject: Noun = (vec![Some("a"), None], 42u64).into();
+        assert_eq!(subject,
+                   cell!(cell!(cell!(atom(0), atom(97)), atom(0), atom(0)), atom(42)));
+    }
+}
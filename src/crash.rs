@@ -0,0 +1,334 @@
+//! crash renders the stack trace of a crashed computation.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use atom::Atom;
+use aura::Aura;
+use parser::Noun;
+
+/// MAX_TRACE is the most trace entries rendered. Longer traces skip the
+/// entries in the middle.
+pub const MAX_TRACE: usize = 1024;
+
+/// Hint is a hint that pushes an entry onto the trace stack while its
+/// formula is computed:
+///
+/// ```text
+/// *[a 11 [%spot c] d]
+/// ```
+///
+/// The entry is the clue *[a c].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hint {
+    /// A source span [path [[line col] [line col]]].
+    Spot,
+    /// A cord or a trap that produces a tank.
+    Mean,
+    /// A scry path.
+    Hunk,
+    /// A cord.
+    Lose,
+}
+
+impl Hint {
+    /// from_tag returns the Hint for a hint tag or None if the tag doesn't
+    /// push a trace entry.
+    pub fn from_tag(tag: &Noun) -> Option<Hint> {
+        match cord(tag).as_ref().map(|s| s.as_str()) {
+            Some("spot") => Some(Hint::Spot),
+            Some("mean") => Some(Hint::Mean),
+            Some("hunk") => Some(Hint::Hunk),
+            Some("lose") => Some(Hint::Lose),
+            _ => None,
+        }
+    }
+}
+
+/// render renders a trace stack ordered from the outermost entry to the
+/// innermost one the way Hoon's mook does. Entries that don't decode are
+/// skipped. A %mean trap is run with mack which returns None if the trap
+/// crashed.
+pub fn render<F>(trace: &[(Hint, Noun)], mut mack: F) -> Vec<String>
+    where F: FnMut(&Noun) -> Option<Noun>
+{
+    let mut lines = Vec::new();
+    let mut entries: Vec<&(Hint, Noun)> = trace.iter().collect();
+    let mut skipped = None;
+    if entries.len() > MAX_TRACE {
+        let skip = entries.len() - MAX_TRACE;
+        entries.drain(MAX_TRACE / 2..MAX_TRACE / 2 + skip);
+        skipped = Some(skip);
+    }
+    for (i, &&(hint, ref clue)) in entries.iter().enumerate() {
+        if i == MAX_TRACE / 2 {
+            if let Some(skip) = skipped {
+                lines.push(format!("[skipped {} frames]", Aura::Ud.render(&Atom::Direct(skip as u64))));
+            }
+        }
+        let line = match (hint, clue) {
+            (Hint::Spot, _) => spot(clue),
+            (Hint::Hunk, _) => path(clue),
+            (Hint::Lose, &Noun::Atom(_)) | (Hint::Mean, &Noun::Atom(_)) => cord(clue),
+            (Hint::Lose, &Noun::Cell(_)) => None,
+            (Hint::Mean, &Noun::Cell(_)) => {
+                Some(mack(clue).map_or("####".to_string(), |tank| ram(&tank)))
+            }
+        };
+        if let Some(line) = line {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// ram renders a tank on a single line. Nouns that aren't tanks render as
+/// nouns.
+pub fn ram(tank: &Noun) -> String {
+    if let &Noun::Atom(_) = tank {
+        return cord(tank).unwrap_or_else(|| tank.to_string());
+    }
+    let (tag, rest) = tank.as_cell().unwrap();
+    let rendered = match cord(tag).as_ref().map(|s| s.as_str()) {
+        Some("leaf") => tape(rest),
+        Some("rose") => {
+            rest.as_cell().and_then(|(style, tanks)| {
+                match (tapes(style, 3), list(tanks)) {
+                    (Some(style), Some(tanks)) => Some(rose(&style[0], &style[1], &style[2], &tanks)),
+                    _ => None,
+                }
+            })
+        }
+        Some("palm") => {
+            rest.as_cell().and_then(|(style, tanks)| {
+                match (tapes(style, 4), list(tanks)) {
+                    (Some(style), Some(tanks)) => {
+                        let open = format!("{}{}", style[1], style[2]);
+                        Some(rose(&style[0], &open, &style[3], &tanks))
+                    }
+                    _ => None,
+                }
+            })
+        }
+        _ => None,
+    };
+    rendered.unwrap_or_else(|| tank.to_string())
+}
+
+fn rose(sep: &str, open: &str, close: &str, tanks: &[Noun]) -> String {
+    let inner: Vec<String> = tanks.iter().map(ram).collect();
+    format!("{}{}{}", open, inner.join(sep), close)
+}
+
+// spot renders a [path [[line col] [line col]]] source span.
+fn spot(clue: &Noun) -> Option<String> {
+    let (p, pint) = match clue.as_cell() {
+        Some(pair) => pair,
+        None => return None,
+    };
+    let nums = pint.as_cell().and_then(|(l, r)| match (l.as_cell(), r.as_cell()) {
+        (Some((a, b)), Some((c, d))) => Some(vec![ud(a), ud(b), ud(c), ud(d)]),
+        _ => None,
+    });
+    match (path(p), nums) {
+        (Some(p), Some(ref n)) if n.iter().all(|n| n.is_some()) => {
+            let n: Vec<&String> = n.iter().map(|n| n.as_ref().unwrap()).collect();
+            Some(format!("{}:<[{} {}].[{} {}]>", p, n[0], n[1], n[2], n[3]))
+        }
+        _ => None,
+    }
+}
+
+fn ud(noun: &Noun) -> Option<String> {
+    match noun {
+        &Noun::Atom(ref a) => Some(Aura::Ud.render(a)),
+        &Noun::Cell(_) => None,
+    }
+}
+
+// path renders a list of knots as /foo/bar.
+fn path(noun: &Noun) -> Option<String> {
+    let knots = match list(noun) {
+        Some(knots) => knots,
+        None => return None,
+    };
+    let mut rendered = String::new();
+    for knot in knots {
+        match cord(&knot) {
+            Some(knot) => {
+                rendered.push('/');
+                rendered.push_str(&knot);
+            }
+            None => return None,
+        }
+    }
+    if rendered.is_empty() {
+        rendered.push('/');
+    }
+    Some(rendered)
+}
+
+// list returns the items of a null terminated list.
+fn list(noun: &Noun) -> Option<Vec<Noun>> {
+    let mut items = Vec::new();
+    let mut next = noun;
+    loop {
+        match next {
+            &Noun::Atom(ref a) if a.as_u64() == Some(0) => return Some(items),
+            &Noun::Atom(_) => return None,
+            &Noun::Cell(ref cell) => {
+                items.push(cell.head().clone());
+                next = cell.tail();
+            }
+        }
+    }
+}
+
+// tapes returns the first n tapes of a tuple of tapes.
+fn tapes(noun: &Noun, n: usize) -> Option<Vec<String>> {
+    let mut rendered = Vec::new();
+    let mut next = noun;
+    for i in 0..n {
+        let item = if i == n - 1 {
+            next
+        } else {
+            match next.as_cell() {
+                Some((head, tail)) => {
+                    next = tail;
+                    head
+                }
+                None => return None,
+            }
+        };
+        match tape(item) {
+            Some(t) => rendered.push(t),
+            None => return None,
+        }
+    }
+    Some(rendered)
+}
+
+// tape decodes a list of utf-8 bytes.
+fn tape(noun: &Noun) -> Option<String> {
+    let items = match list(noun) {
+        Some(items) => items,
+        None => return None,
+    };
+    let mut bytes = Vec::with_capacity(items.len());
+    for c in items {
+        match c {
+            Noun::Atom(ref a) => {
+                match a.as_u64() {
+                    Some(b) if b < 256 => bytes.push(b as u8),
+                    _ => return None,
+                }
+            }
+            Noun::Cell(_) => return None,
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// cord decodes an atom of utf-8 bytes.
+fn cord(noun: &Noun) -> Option<String> {
+    match noun {
+        &Noun::Atom(ref a) => {
+            let mut bytes = a.to_bytes_le();
+            while bytes.last() == Some(&0) {
+                bytes.pop();
+            }
+            String::from_utf8(bytes).ok()
+        }
+        &Noun::Cell(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod crash_tests {
+    use crash::{Hint, MAX_TRACE, ram, render};
+    use parser::{Noun, Parser, atom};
+    use tokenizer::tokenizer_tests::MockReader;
+
+    fn parse_noun(expr: &str) -> Noun {
+        let reader = MockReader::new(vec![expr.to_string()]);
+        Parser::new(Box::new(reader)).parse().unwrap()
+    }
+
+    // text encodes s as a cord.
+    fn text(s: &str) -> Noun {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.reverse();
+        let mut n = 0u64;
+        for b in bytes {
+            n = n << 8 | b as u64;
+        }
+        atom(n)
+    }
+
+    // tape encodes s as a tape.
+    fn tape(s: &str) -> Noun {
+        s.bytes().rev().fold(atom(0), |tail, b| Noun::cons(atom(b as u64), tail))
+    }
+
+    #[test]
+    fn test_ram() {
+        let leaf = Noun::cons(text("leaf"), tape("foo"));
+        assert_eq!(ram(&leaf), "foo");
+        let rose = cell!(text("rose"),
+                         cell!(tape(" "), tape("["), tape("]")),
+                         cell!(leaf.clone(), leaf.clone(), atom(0)));
+        assert_eq!(ram(&rose), "[foo foo]");
+        let palm = cell!(text("palm"),
+                         cell!(tape("  "), tape("<"), tape("|"), tape(">")),
+                         cell!(leaf.clone(), atom(0)));
+        assert_eq!(ram(&palm), "<|foo>");
+        assert_eq!(ram(&text("bar")), "bar");
+        assert_eq!(ram(&cell!(atom(1), atom(2))), "[1 2]");
+    }
+
+    #[test]
+    fn test_render() {
+        let span = cell!(cell!(atom(2), atom(1)), cell!(atom(1234), atom(5)));
+        let spot = Noun::cons(cell!(text("sys"), text("hoon"), atom(0)), span);
+        let trap = cell!(atom(1), atom(0));
+        let trace = vec![(Hint::Spot, spot),
+                         (Hint::Hunk, cell!(text("foo"), text("bar"), atom(0))),
+                         (Hint::Lose, text("lost")),
+                         (Hint::Mean, text("mean")),
+                         (Hint::Mean, trap.clone()),
+                         (Hint::Mean, trap),
+                         (Hint::Spot, atom(0))];
+        let mut tank = Some(Noun::cons(text("leaf"), tape("trapped")));
+        let lines = render(&trace, |_| tank.take());
+        assert_eq!(lines,
+                   vec!["/sys/hoon:<[2 1].[1.234 5]>",
+                        "/foo/bar",
+                        "lost",
+                        "mean",
+                        "trapped",
+                        "####"]);
+        assert_eq!(Hint::from_tag(&text("spot")), Some(Hint::Spot));
+        assert_eq!(Hint::from_tag(&text("fast")), None);
+    }
+
+    #[test]
+    fn test_render_long() {
+        let trace: Vec<(Hint, Noun)> =
+            (0..MAX_TRACE + 10).map(|i| (Hint::Lose, text(&i.to_string()))).collect();
+        let lines = render(&trace, |_| None);
+        assert_eq!(lines.len(), MAX_TRACE + 1);
+        assert_eq!(lines[MAX_TRACE / 2 - 1], "511");
+        assert_eq!(lines[MAX_TRACE / 2], "[skipped 10 frames]");
+        assert_eq!(lines[MAX_TRACE / 2 + 1], "522");
+        assert_eq!(lines[MAX_TRACE], "1033");
+    }
+}
@@ -0,0 +1,366 @@
+//! debug implements an interactive step debugger for nock computations.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use atom::Atom;
+use nock::{Execution, Interpreter, Limits};
+use parser::Noun;
+use pretty::Printer;
+
+/// Pattern matches the shape of a noun.
+///
+/// Patterns are written like nouns with _ matching anything so [_ 42]
+/// matches any cell whose tail is 42 and [[_ _] _] matches any cell whose
+/// head is a cell. Cells autocons like nouns do.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Pattern {
+    Any,
+    Atom(Atom),
+    Cell(Box<Pattern>, Box<Pattern>),
+}
+
+impl Pattern {
+    /// matches returns true if the noun has the pattern's shape.
+    pub fn matches(&self, noun: &Noun) -> bool {
+        match (self, noun) {
+            (&Pattern::Any, _) => true,
+            (&Pattern::Atom(ref a), &Noun::Atom(ref b)) => a == b,
+            (&Pattern::Cell(ref head, ref tail), &Noun::Cell(ref cell)) => {
+                head.matches(cell.head()) && tail.matches(cell.tail())
+            }
+            _ => false,
+        }
+    }
+}
+
+// parse_pattern parses a pattern from the start of chars.
+fn parse_pattern(chars: &mut ::std::iter::Peekable<::std::str::Chars>) -> Result<Pattern, String> {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+    match chars.next() {
+        Some('_') => Ok(Pattern::Any),
+        Some('[') => {
+            let mut items = Vec::new();
+            loop {
+                while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    break;
+                }
+                items.push(try!(parse_pattern(chars)));
+            }
+            if items.len() < 2 {
+                return Err("Cells must have at least 2 patterns".to_string());
+            }
+            let mut pattern = items.pop().unwrap();
+            while let Some(head) = items.pop() {
+                pattern = Pattern::Cell(Box::new(head), Box::new(pattern));
+            }
+            Ok(pattern)
+        }
+        Some(c) if c.is_digit(10) => {
+            let mut digits = c.to_string();
+            while chars.peek().map_or(false, |c| c.is_digit(10) || *c == '.') {
+                let c = chars.next().unwrap();
+                if c != '.' {
+                    digits.push(c);
+                }
+            }
+            Atom::from_str(&digits).map(Pattern::Atom).map_err(|e| e.to_string())
+        }
+        Some(c) => Err(format!("Unexpected '{}' in pattern", c)),
+        None => Err("Unexpected end of pattern".to_string()),
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().peekable();
+        let pattern = try!(parse_pattern(&mut chars));
+        if chars.any(|c| !c.is_whitespace()) {
+            return Err(format!("Unexpected input after pattern {}", s));
+        }
+        Ok(pattern)
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            &Pattern::Any => write!(fmt, "_"),
+            &Pattern::Atom(ref a) => write!(fmt, "{}", a),
+            &Pattern::Cell(ref head, ref tail) => {
+                try!(write!(fmt, "[{}", head));
+                let mut next = tail;
+                while let &Pattern::Cell(ref head, ref tail) = next.as_ref() {
+                    try!(write!(fmt, " {}", head));
+                    next = tail;
+                }
+                write!(fmt, " {}]", next)
+            }
+        }
+    }
+}
+
+const HELP: &'static str = "\
+s, step              take one step
+n, next              step over the current formula
+c, continue          run until a breakpoint or the computation stops
+b, break OP          break before reducing a formula with opcode OP
+m, match PATTERN     break when the subject matches PATTERN like [_ 42]
+clear                remove all breakpoints
+p, print             print the subject, formula and axis path
+q, quit              abandon the computation
+h, help              print this help";
+
+/// Debugger steps through a computation under the control of commands.
+pub struct Debugger {
+    execution: Execution,
+    printer: Printer,
+    break_ops: Vec<u64>,
+    break_subjects: Vec<Pattern>,
+    stopped: bool,
+}
+
+impl Debugger {
+    /// new starts debugging a nock expression of type [subj formula] or
+    /// atom. Subjects and formulas are printed with printer.
+    pub fn new(interpreter: &mut Interpreter, noun: Noun, limits: Limits, printer: Printer) -> Self {
+        Debugger {
+            execution: interpreter.start(noun, limits),
+            printer: printer,
+            break_ops: Vec::new(),
+            break_subjects: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    /// is_stopped returns true once the computation finished, crashed or
+    /// was abandoned.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// position describes the step the computation is paused before.
+    pub fn position(&self) -> String {
+        match self.execution.formula() {
+            Some(formula) => {
+                format!("step {} depth {}: {}",
+                        self.execution.steps(),
+                        self.execution.depth(),
+                        self.printer.render(formula))
+            }
+            None => "stopped".to_string(),
+        }
+    }
+
+    /// command runs a debugger command and returns what it prints.
+    pub fn command(&mut self, interpreter: &mut Interpreter, line: &str) -> String {
+        if self.stopped {
+            return "The computation has stopped".to_string();
+        }
+        let mut words = line.trim().splitn(2, char::is_whitespace);
+        let arg = words.clone().nth(1).unwrap_or("").trim();
+        match words.next().unwrap_or("") {
+            "" | "s" | "step" => self.run(interpreter, None, false),
+            "n" | "next" => {
+                let depth = self.execution.depth();
+                self.run(interpreter, Some(depth), true)
+            }
+            "c" | "continue" => self.run(interpreter, Some(0), false),
+            "b" | "break" => {
+                match u64::from_str(arg) {
+                    Ok(op) => {
+                        self.break_ops.push(op);
+                        format!("Breaking on opcode {}", op)
+                    }
+                    Err(_) => format!("break expects an opcode not {}", arg),
+                }
+            }
+            "m" | "match" => {
+                match Pattern::from_str(arg) {
+                    Ok(pattern) => {
+                        let msg = format!("Breaking on subjects matching {}", pattern);
+                        self.break_subjects.push(pattern);
+                        msg
+                    }
+                    Err(err) => err,
+                }
+            }
+            "clear" => {
+                self.break_ops.clear();
+                self.break_subjects.clear();
+                "Cleared all breakpoints".to_string()
+            }
+            "p" | "print" => {
+                let path: Vec<String> = self.execution
+                    .axis_path()
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect();
+                format!("subject: {}\nformula: {}\naxis path: /{}",
+                        self.execution.subject().map_or("".to_string(), |s| self.printer.render(s)),
+                        self.execution.formula().map_or("".to_string(), |f| self.printer.render(f)),
+                        path.join("/"))
+            }
+            "q" | "quit" => {
+                self.stopped = true;
+                "Abandoned the computation".to_string()
+            }
+            "h" | "help" => HELP.to_string(),
+            cmd => format!("Unknown debugger command {}. Type help for the commands", cmd),
+        }
+    }
+
+    // at_breakpoint returns true if the next step matches a breakpoint.
+    fn at_breakpoint(&self) -> bool {
+        if let Some(&Noun::Cell(ref cell)) = self.execution.formula() {
+            if let &Noun::Atom(ref op) = cell.head() {
+                if op.as_u64().map_or(false, |op| self.break_ops.contains(&op)) {
+                    return true;
+                }
+            }
+        }
+        match self.execution.subject() {
+            Some(subject) => self.break_subjects.iter().any(|p| p.matches(subject)),
+            None => false,
+        }
+    }
+
+    // run steps the computation until it stops. With until_depth it also
+    // stops at a breakpoint or, stepping over, once the computation at that
+    // depth has produced its product. Otherwise it takes a single step.
+    fn run(&mut self, interpreter: &mut Interpreter, until_depth: Option<usize>, over: bool) -> String {
+        loop {
+            match interpreter.advance(&mut self.execution) {
+                Ok(Some(computed)) => {
+                    self.stopped = true;
+                    return format!("product: {}", self.printer.render(&computed.product));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    self.stopped = true;
+                    return format!("{}", err);
+                }
+            }
+            let done = match until_depth {
+                None => true,
+                Some(depth) => {
+                    (over && self.execution.produced().map_or(false, |d| d <= depth)) ||
+                    self.at_breakpoint()
+                }
+            };
+            if done {
+                return self.position();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use std::str::FromStr;
+    use debug::{Debugger, Pattern};
+    use nock::{Interpreter, Limits, Spec};
+    use parser::{Noun, atom};
+    use pretty::Printer;
+
+    #[test]
+    fn test_pattern() {
+        let pattern = Pattern::from_str("[_ 4 _]").unwrap();
+        assert_eq!(pattern.to_string(), "[_ 4 _]");
+        assert!(pattern.matches(&cell!(atom(1), atom(4), atom(5))));
+        assert!(pattern.matches(&cell!(cell!(atom(1), atom(2)), atom(4), atom(5), atom(6))));
+        assert!(!pattern.matches(&cell!(atom(1), atom(3), atom(5))));
+        assert!(!pattern.matches(&atom(4)));
+        assert!(Pattern::from_str("_").unwrap().matches(&atom(4)));
+        assert_eq!(Pattern::from_str("1.000").unwrap(), Pattern::Atom(1000.into()));
+        assert!(Pattern::from_str("[1]").is_err());
+        assert!(Pattern::from_str("[1 2").is_err());
+        assert!(Pattern::from_str("[1 2] 3").is_err());
+    }
+
+    fn debugger(interpreter: &mut Interpreter, subject: Noun, formula: Noun) -> Debugger {
+        Debugger::new(interpreter,
+                      Noun::cons(subject, formula),
+                      Limits::default(),
+                      Printer::new(80, None))
+    }
+
+    #[test]
+    fn test_step() {
+        let mut interpreter = Interpreter::new(Spec::Nock4K);
+        // *[42 [7 [4 0 1] 4 0 1] 0 1]
+        let formula = parse("[[7 [4 0 1] 4 0 1] 0 1]");
+        let mut dbg = debugger(&mut interpreter, atom(42), formula);
+        assert_eq!(dbg.position(), "step 0 depth 0: [[7 [4 0 1] 4 0 1] 0 1]");
+        assert_eq!(dbg.command(&mut interpreter, "s"), "step 1 depth 1: [7 [4 0 1] 4 0 1]");
+        assert_eq!(dbg.command(&mut interpreter, "s"), "step 2 depth 2: [4 0 1]");
+        assert_eq!(dbg.command(&mut interpreter, "print"),
+                   "subject: 42\nformula: [4 0 1]\naxis path: /2/6");
+        // Stepping over the new subject pauses before the body of 7.
+        assert_eq!(dbg.command(&mut interpreter, "next"), "step 4 depth 1: [4 0 1]");
+        // Stepping over the body of 7 finishes the 7 it replaced.
+        assert_eq!(dbg.command(&mut interpreter, "n"), "step 6 depth 1: [0 1]");
+        assert_eq!(dbg.command(&mut interpreter, "c"), "product: [44 42]");
+        assert!(dbg.is_stopped());
+
+        // Stepping over 7 runs its body too.
+        let mut dbg = debugger(&mut interpreter, atom(42), parse("[[7 [4 0 1] 4 0 1] 0 1]"));
+        assert_eq!(dbg.command(&mut interpreter, "s"), "step 1 depth 1: [7 [4 0 1] 4 0 1]");
+        assert_eq!(dbg.command(&mut interpreter, "n"), "step 6 depth 1: [0 1]");
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut interpreter = Interpreter::new(Spec::Nock4K);
+        // *[[1 2] 8 [4 0 2] 5 [0 2] 0 6] pushes 2 onto the subject and
+        // compares it with 1.
+        let formula = parse("[8 [4 0 2] [5 [0 2] [0 6]]]");
+        let mut dbg = debugger(&mut interpreter, cell!(atom(1), atom(2)), formula.clone());
+        assert_eq!(dbg.command(&mut interpreter, "break 5"), "Breaking on opcode 5");
+        assert_eq!(dbg.command(&mut interpreter, "c"), "step 3 depth 0: [5 [0 2] 0 6]");
+        assert_eq!(dbg.command(&mut interpreter, "c"), "product: 1");
+
+        let mut dbg = debugger(&mut interpreter, cell!(atom(1), atom(2)), formula);
+        dbg.command(&mut interpreter, "match [2 _]");
+        assert_eq!(dbg.command(&mut interpreter, "c"), "step 3 depth 0: [5 [0 2] 0 6]");
+        dbg.command(&mut interpreter, "clear");
+        assert_eq!(dbg.command(&mut interpreter, "c"), "product: 1");
+        assert_eq!(dbg.command(&mut interpreter, "c"), "The computation has stopped");
+    }
+
+    #[test]
+    fn test_crash_stops() {
+        let mut interpreter = Interpreter::new(Spec::Nock4K);
+        let mut dbg = debugger(&mut interpreter, atom(42), parse("[4 0 2]"));
+        assert!(dbg.command(&mut interpreter, "c").contains("!! Can't take axis 2 of 42"));
+        assert!(dbg.is_stopped());
+    }
+
+    fn parse(expr: &str) -> Noun {
+        use parser::Parser;
+        use tokenizer::tokenizer_tests::MockReader;
+        let reader = MockReader::new(vec![expr.to_string()]);
+        Parser::new(Box::new(reader)).parse().unwrap()
+    }
+}
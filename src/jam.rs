@@ -0,0 +1,314 @@
+//! jam implements Urbit's jam and cue binary serialization for nouns.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fmt::Display;
+
+use atom::Atom;
+use parser::Noun;
+
+make_error!(CueError, "CueError: {}\n");
+
+// BitWriter accumulates bits LSB first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bit: bool) {
+        if self.len % 8 == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    // push_bits writes the low `count` bits of the LSB first bytes.
+    fn push_bits(&mut self, bytes: &[u8], count: usize) {
+        for i in 0..count {
+            self.push(bytes.get(i / 8).map_or(false, |b| b & (1 << (i % 8)) != 0));
+        }
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        // An atom has no trailing zeros.
+        while self.bytes.last() == Some(&0) {
+            self.bytes.pop();
+        }
+        self.bytes
+    }
+}
+
+fn usize_bytes(u: usize) -> Vec<u8> {
+    Atom::Direct(u as u64).to_bytes_le()
+}
+
+fn bit_len(u: usize) -> usize {
+    (0usize.leading_zeros() - u.leading_zeros()) as usize
+}
+
+// mat writes the self delimiting length encoding of an atom.
+fn mat(w: &mut BitWriter, a: &Atom) {
+    let b = a.bit_len();
+    if b == 0 {
+        w.push(true);
+        return;
+    }
+    let c = bit_len(b);
+    for _ in 0..c {
+        w.push(false);
+    }
+    w.push(true);
+    w.push_bits(&usize_bytes(b), c - 1);
+    w.push_bits(&a.to_bytes_le(), b);
+}
+
+/// jam_bytes serializes a noun to the LSB first bytes of its jam atom.
+pub fn jam_bytes(noun: &Noun) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    let mut seen: HashMap<Noun, usize> = HashMap::new();
+    let mut stack = vec![noun.clone()];
+    while let Some(noun) = stack.pop() {
+        if let Some(&offset) = seen.get(&noun) {
+            // Prefer the atom itself when it is no longer than the backref.
+            if let Noun::Atom(ref a) = noun {
+                if a.bit_len() <= bit_len(offset) {
+                    w.push(false);
+                    mat(&mut w, a);
+                    continue;
+                }
+            }
+            w.push(true);
+            w.push(true);
+            mat(&mut w, &Atom::Direct(offset as u64));
+            continue;
+        }
+        seen.insert(noun.clone(), w.len);
+        match noun {
+            Noun::Atom(ref a) => {
+                w.push(false);
+                mat(&mut w, a);
+            }
+            Noun::Cell(ref cell) => {
+                w.push(true);
+                w.push(false);
+                stack.push(cell.tail().clone());
+                stack.push(cell.head().clone());
+            }
+        }
+    }
+    w.into_bytes()
+}
+
+/// jam serializes a noun to an atom.
+pub fn jam(noun: &Noun) -> Atom {
+    Atom::from_bytes_le(&jam_bytes(noun))
+}
+
+// BitReader reads bits LSB first. Reading past the end yields zeros just
+// like reading past the end of an atom.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&self, i: usize) -> bool {
+        self.bytes.get(i / 8).map_or(false, |b| b & (1 << (i % 8)) != 0)
+    }
+
+    // remaining returns the number of bits left to read.
+    fn remaining(&self) -> usize {
+        (self.bytes.len() * 8).saturating_sub(self.pos)
+    }
+
+    fn next(&mut self) -> bool {
+        let bit = self.bit(self.pos);
+        self.pos += 1;
+        bit
+    }
+
+    fn take_atom(&mut self, count: usize) -> Atom {
+        let mut bytes = vec![0u8; (count + 7) / 8];
+        for i in 0..count {
+            if self.next() {
+                bytes[i / 8] |= 1 << (i % 8);
+            }
+        }
+        Atom::from_bytes_le(&bytes)
+    }
+
+    fn take_usize(&mut self, count: usize) -> Result<usize, CueError> {
+        if count > 63 {
+            return Err(CueError::new(format!("!! Length too large at bit {}", self.pos)));
+        }
+        Ok(self.take_atom(count).as_u64().unwrap() as usize)
+    }
+
+    // rub reads an atom written by mat.
+    fn rub(&mut self) -> Result<Atom, CueError> {
+        let start = self.pos;
+        let mut c = 0;
+        while !self.next() {
+            c += 1;
+            if self.pos > self.bytes.len() * 8 {
+                return Err(CueError::new(format!("!! Unterminated length at bit {}", start)));
+            }
+        }
+        if c == 0 {
+            return Ok(Atom::Direct(0));
+        }
+        let low = try!(self.take_usize(c - 1));
+        let e = (1 << (c - 1)) + low;
+        // A well formed jam always ends on the top bit of an atom so an atom
+        // that runs past the end is malformed.
+        if e > self.remaining() {
+            return Err(CueError::new(format!("!! Atom of {} bits runs past the end at bit {}", e, start)));
+        }
+        Ok(self.take_atom(e))
+    }
+}
+
+enum Frame {
+    Head(usize),
+    Tail(usize, Noun),
+}
+
+/// cue deserializes a noun from the LSB first bytes of a jam atom.
+pub fn cue_bytes(bytes: &[u8]) -> Result<Noun, CueError> {
+    let mut r = BitReader {
+        bytes: bytes,
+        pos: 0,
+    };
+    let mut seen: HashMap<usize, Noun> = HashMap::new();
+    let mut stack = Vec::new();
+    loop {
+        let start = r.pos;
+        let mut result = if !r.next() {
+            let noun = Noun::Atom(try!(r.rub()));
+            seen.insert(start, noun.clone());
+            noun
+        } else if !r.next() {
+            stack.push(Frame::Head(start));
+            continue;
+        } else {
+            let offset = try!(r.rub());
+            let noun = offset.as_u64().and_then(|o| seen.get(&(o as usize)));
+            match noun {
+                Some(noun) => noun.clone(),
+                None => {
+                    return Err(CueError::new(format!("!! Bad backreference {} at bit {}",
+                                                     offset,
+                                                     start)))
+                }
+            }
+        };
+        loop {
+            match stack.pop() {
+                None => return Ok(result),
+                Some(Frame::Head(pos)) => {
+                    stack.push(Frame::Tail(pos, result));
+                    break;
+                }
+                Some(Frame::Tail(pos, head)) => {
+                    result = Noun::cons(head, result);
+                    seen.insert(pos, result.clone());
+                }
+            }
+        }
+    }
+}
+
+/// cue deserializes a noun from a jam atom.
+pub fn cue(a: &Atom) -> Result<Noun, CueError> {
+    cue_bytes(&a.to_bytes_le())
+}
+
+#[cfg(test)]
+mod jam_tests {
+    use atom::Atom;
+    use jam::{jam, cue, jam_bytes, cue_bytes};
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_jam_spec_vectors() {
+        // Values from hoon's own jam.
+        let cases = vec![(atom(0), 2),
+                         (atom(1), 12),
+                         (atom(2), 72),
+                         (cell!(atom(0), atom(0)), 41),
+                         (cell!(atom(1), atom(2)), 4657),
+                         (cell!(atom(0), atom(19)), 39689),
+                         (cell!(cell!(atom(1), atom(2)), atom(1), atom(2)), 4835525),
+                         (cell!(atom(1), atom(2), atom(3)), 3426417)];
+        for (noun, expected) in cases {
+            assert_eq!(jam(&noun), Atom::Direct(expected));
+        }
+    }
+
+    #[test]
+    fn test_cue_round_trip() {
+        let nouns = vec![atom(0),
+                         atom(42),
+                         cell!(atom(1), atom(2), atom(3)),
+                         cell!(cell!(atom(1), atom(2)), cell!(atom(1), atom(2))),
+                         cell!(atom(123456789), atom(123456789), cell!(atom(9), atom(9)))];
+        for noun in nouns {
+            assert_eq!(cue(&jam(&noun)).unwrap(), noun);
+            assert_eq!(cue_bytes(&jam_bytes(&noun)).unwrap(), noun);
+        }
+    }
+
+    #[test]
+    fn test_cue_deep_list() {
+        let mut list = atom(0);
+        for i in 0..100000 {
+            list = Noun::cons(atom(i % 7), list);
+        }
+        assert_eq!(cue_bytes(&jam_bytes(&list)).unwrap(), list);
+    }
+
+    #[test]
+    fn test_cue_bad_backref() {
+        // A backref tag pointing at bit 5 which was never decoded.
+        assert!(cue_bytes(&[0b0100_1111, 0b0]).is_err());
+    }
+
+    #[test]
+    fn test_cue_truncated() {
+        let mut bytes = jam_bytes(&cell!(atom(1), atom(123456789)));
+        bytes.pop();
+        assert!(cue_bytes(&bytes).is_err());
+        // An atom tag with a length prefix of nearly 2^64 bits and nothing
+        // after it.
+        let mut bytes = vec![0u8; 8];
+        bytes.push(0xfe);
+        bytes.extend(vec![0xffu8; 7]);
+        bytes.push(0x01);
+        let err = cue_bytes(&bytes).unwrap_err();
+        assert!(err.to_string().contains("runs past the end"));
+    }
+}
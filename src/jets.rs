@@ -0,0 +1,364 @@
+//! jets implements native replacements for well known nock gates.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::mem;
+
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive, Zero};
+
+use atom::Atom;
+use parser::{Noun, atom};
+
+/// A Jet computes a gate's product from its sample. Returning None punts to
+/// the gate's nock which is also how a jet reports a crash.
+pub type Jet = fn(&Noun) -> Option<Noun>;
+
+/// Mismatch is a jet product that differs from the product of the gate's
+/// nock.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub name: String,
+    pub sample: Noun,
+    pub jet: Noun,
+    pub nock: Noun,
+}
+
+/// Jets is a registry of jets and the gates they replace.
+///
+/// A gate is a core [battery sample context] whose battery is a single arm.
+/// Its battery is matched to a jet by name. Batteries are named either
+/// explicitly with name_battery or by the interpreter when a core is
+/// computed under a %fast hint:
+///
+/// ```text
+/// *[a 11 [%fast c] d]
+/// ```
+///
+/// The clue *[a c] is the name of the core *[a d] or a cell whose head is the
+/// name like the [%dec [0 7] ~] Hoon produces. Calling arm 2 of a named core
+/// with 9 runs the jet on the core's sample at axis 6 instead.
+pub struct Jets {
+    jets: HashMap<String, Jet>,
+    batteries: HashMap<Noun, String>,
+    verify: bool,
+    // A frozen registry is only read from.
+    frozen: bool,
+    mismatches: Vec<Mismatch>,
+}
+
+impl Jets {
+    /// new constructs an empty registry.
+    pub fn new() -> Self {
+        Jets {
+            jets: HashMap::new(),
+            batteries: HashMap::new(),
+            verify: false,
+            frozen: false,
+            mismatches: Vec::new(),
+        }
+    }
+
+    /// with_defaults constructs a registry with the standard jets dec, add,
+    /// sub, mul, div, mod, lth, cut, cat, met and mug.
+    pub fn with_defaults() -> Self {
+        let mut jets = Jets::new();
+        jets.register("dec", dec);
+        jets.register("add", add);
+        jets.register("sub", sub);
+        jets.register("mul", mul);
+        jets.register("div", div);
+        jets.register("mod", modulo);
+        jets.register("lth", lth);
+        jets.register("cut", cut);
+        jets.register("cat", cat);
+        jets.register("met", met);
+        jets.register("mug", mug);
+        jets
+    }
+
+    /// register registers a jet for the gates named name.
+    pub fn register<S: Into<String>>(&mut self, name: S, jet: Jet) {
+        self.jets.insert(name.into(), jet);
+    }
+
+    /// name_battery names the gates with battery.
+    pub fn name_battery<S: Into<String>>(&mut self, battery: Noun, name: S) {
+        self.batteries.insert(battery, name.into());
+    }
+
+    /// set_verify turns verification on or off. When verifying the
+    /// interpreter runs the gate's nock after every jet and records a
+    /// Mismatch if their products differ. The nock product is used.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// is_verifying returns true if verification is on.
+    pub fn is_verifying(&self) -> bool {
+        self.verify
+    }
+
+    /// take_mismatches returns and forgets the mismatches recorded so far.
+    pub fn take_mismatches(&mut self) -> Vec<Mismatch> {
+        self.mismatches.drain(..).collect()
+    }
+
+    pub(crate) fn mismatch(&mut self, mismatch: Mismatch) {
+        if !self.frozen {
+            self.mismatches.push(mismatch);
+        }
+    }
+
+    // freeze stops %fast hints naming batteries and mismatches being
+    // recorded so a computation can use the jets without changing them.
+    pub(crate) fn freeze(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    // fast names the battery of core with the name in a %fast clue.
+    pub(crate) fn fast(&mut self, clue: &Noun, core: &Noun) {
+        if self.frozen {
+            return;
+        }
+        let name = match clue.as_cell() {
+            Some((name, _)) => name,
+            None => clue,
+        };
+        if let (&Noun::Atom(ref name), Some((battery, _))) = (name, core.as_cell()) {
+            if let Ok(name) = String::from_utf8(name.to_bytes_le()) {
+                self.name_battery(battery.clone(), name);
+            }
+        }
+    }
+
+    // find returns the name and jet for calling arm axis of core.
+    pub(crate) fn find(&self, core: &Noun, axis: &Atom) -> Option<(String, Jet)> {
+        if self.jets.is_empty() || axis.as_u64() != Some(2) {
+            return None;
+        }
+        let battery = match core.as_cell() {
+            Some((battery, _)) => battery,
+            None => return None,
+        };
+        self.batteries
+            .get(battery)
+            .and_then(|name| self.jets.get(name).map(|jet| (name.clone(), *jet)))
+    }
+}
+
+// num returns an atom noun as a BigUint.
+fn num(noun: &Noun) -> Option<BigUint> {
+    match noun {
+        &Noun::Atom(ref a) => Some(a.to_biguint()),
+        &Noun::Cell(_) => None,
+    }
+}
+
+// small returns an atom noun as a usize if it fits.
+fn small(noun: &Noun) -> Option<usize> {
+    num(noun).and_then(|n| n.to_usize())
+}
+
+// pair returns the two atoms of a [a b] sample.
+fn pair(sample: &Noun) -> Option<(BigUint, BigUint)> {
+    sample.as_cell().and_then(|(a, b)| match (num(a), num(b)) {
+        (Some(a), Some(b)) => Some((a, b)),
+        _ => None,
+    })
+}
+
+fn noun(n: BigUint) -> Noun {
+    Noun::Atom(Atom::from_biguint(n))
+}
+
+// bits returns the number of bits in 2^bloq bit blocks or None if that
+// doesn't fit in a usize.
+fn bits(bloq: usize, blocks: usize) -> Option<usize> {
+    if blocks == 0 {
+        return Some(0);
+    }
+    if bloq >= 8 * mem::size_of::<usize>() {
+        return None;
+    }
+    blocks.checked_mul(1 << bloq)
+}
+
+// met_bits returns the number of 2^bloq bit blocks in a.
+fn met_bits(bloq: usize, a: &BigUint) -> usize {
+    let size = 1 << bloq;
+    (a.bits() + size - 1) / size
+}
+
+/// dec a is a - 1. It crashes on 0.
+pub fn dec(sample: &Noun) -> Option<Noun> {
+    num(sample).and_then(|a| if a.is_zero() { None } else { Some(noun(a - BigUint::one())) })
+}
+
+/// add [a b] is a + b.
+pub fn add(sample: &Noun) -> Option<Noun> {
+    pair(sample).map(|(a, b)| noun(a + b))
+}
+
+/// sub [a b] is a - b. It crashes if b is larger than a.
+pub fn sub(sample: &Noun) -> Option<Noun> {
+    pair(sample).and_then(|(a, b)| if b > a { None } else { Some(noun(a - b)) })
+}
+
+/// mul [a b] is a * b.
+pub fn mul(sample: &Noun) -> Option<Noun> {
+    pair(sample).map(|(a, b)| noun(a * b))
+}
+
+/// div [a b] is a / b rounded down. It crashes if b is 0.
+pub fn div(sample: &Noun) -> Option<Noun> {
+    pair(sample).and_then(|(a, b)| if b.is_zero() { None } else { Some(noun(a / b)) })
+}
+
+/// modulo is the mod jet. mod [a b] is the remainder of a / b. It crashes if
+/// b is 0.
+pub fn modulo(sample: &Noun) -> Option<Noun> {
+    pair(sample).and_then(|(a, b)| if b.is_zero() { None } else { Some(noun(a % b)) })
+}
+
+/// lth [a b] is the loobean a < b.
+pub fn lth(sample: &Noun) -> Option<Noun> {
+    pair(sample).map(|(a, b)| if a < b { atom(0) } else { atom(1) })
+}
+
+/// met [a b] is the number of 2^a bit blocks in b.
+pub fn met(sample: &Noun) -> Option<Noun> {
+    let (a, b) = match sample.as_cell() {
+        Some(pair) => pair,
+        None => return None,
+    };
+    match (small(a), num(b)) {
+        (Some(a), Some(b)) if a < 32 => Some(atom(met_bits(a, &b) as u64)),
+        _ => None,
+    }
+}
+
+/// cut [a [b c] d] is the c 2^a bit blocks of d starting at block b.
+pub fn cut(sample: &Noun) -> Option<Noun> {
+    let (a, rest) = match sample.as_cell() {
+        Some(pair) => pair,
+        None => return None,
+    };
+    let (bc, d) = match rest.as_cell() {
+        Some(pair) => pair,
+        None => return None,
+    };
+    let (b, c) = match bc.as_cell() {
+        Some(pair) => pair,
+        None => return None,
+    };
+    let (a, c, d) = match (small(a), num(c), num(d)) {
+        (Some(a), Some(c), Some(d)) => (a, c, d),
+        _ => return None,
+    };
+    // Leave an offset too large to shift by to the gate's nock.
+    let start = match small(b).and_then(|b| bits(a, b)) {
+        Some(start) => start,
+        None => return None,
+    };
+    if start >= d.bits() {
+        // Everything past the end of d is 0.
+        return Some(atom(0));
+    }
+    let rest = d >> start;
+    match c.to_usize().and_then(|c| bits(a, c)) {
+        Some(len) if len < rest.bits() => {
+            Some(noun(rest & ((BigUint::one() << len) - BigUint::one())))
+        }
+        // A length past the end of d takes the rest of it.
+        _ => Some(noun(rest)),
+    }
+}
+
+/// cat [a b c] is b followed by c in 2^a bit blocks.
+pub fn cat(sample: &Noun) -> Option<Noun> {
+    let (a, rest) = match sample.as_cell() {
+        Some(pair) => pair,
+        None => return None,
+    };
+    let (a, (b, c)) = match (small(a), pair(rest)) {
+        (Some(a), Some(bc)) if a < 32 => (a, bc),
+        _ => return None,
+    };
+    let shift = met_bits(a, &b) << a;
+    Some(noun(b + (c << shift)))
+}
+
+/// mug a is the mug of the noun a.
+pub fn mug(sample: &Noun) -> Option<Noun> {
+    Some(atom(sample.mug() as u64))
+}
+
+#[cfg(test)]
+mod jets_tests {
+    use std::str::FromStr;
+    use atom::Atom;
+    use jets;
+    use jets::Jets;
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(jets::dec(&atom(42)), Some(atom(41)));
+        assert_eq!(jets::dec(&atom(0)), None);
+        assert_eq!(jets::add(&cell!(atom(2), atom(3))), Some(atom(5)));
+        assert_eq!(jets::sub(&cell!(atom(5), atom(3))), Some(atom(2)));
+        assert_eq!(jets::sub(&cell!(atom(3), atom(5))), None);
+        assert_eq!(jets::mul(&cell!(atom(5), atom(3))), Some(atom(15)));
+        assert_eq!(jets::div(&cell!(atom(17), atom(5))), Some(atom(3)));
+        assert_eq!(jets::div(&cell!(atom(17), atom(0))), None);
+        assert_eq!(jets::modulo(&cell!(atom(17), atom(5))), Some(atom(2)));
+        assert_eq!(jets::lth(&cell!(atom(1), atom(2))), Some(atom(0)));
+        assert_eq!(jets::lth(&cell!(atom(2), atom(2))), Some(atom(1)));
+        assert_eq!(jets::add(&atom(2)), None);
+        let big = Noun::Atom(Atom::from_str("18446744073709551616").unwrap());
+        assert_eq!(jets::dec(&big), Some(atom(::std::u64::MAX)));
+    }
+
+    #[test]
+    fn test_bits() {
+        // (met 3 0x1234) is 2 bytes and (met 0 0x1234) is 13 bits.
+        assert_eq!(jets::met(&cell!(atom(3), atom(0x1234))), Some(atom(2)));
+        assert_eq!(jets::met(&cell!(atom(0), atom(0x1234))), Some(atom(13)));
+        assert_eq!(jets::met(&cell!(atom(3), atom(0))), Some(atom(0)));
+        // (cut 3 [1 2] 0x12345678) is 0x3456.
+        let sample = cell!(atom(3), cell!(atom(1), atom(2)), atom(0x12345678));
+        assert_eq!(jets::cut(&sample), Some(atom(0x3456)));
+        let sample = cell!(atom(32), cell!(atom(0), atom(1)), atom(5));
+        assert_eq!(jets::cut(&sample), Some(atom(5)));
+        // (cat 3 0x12 0x34) is 0x3412.
+        assert_eq!(jets::cat(&cell!(atom(3), atom(0x12), atom(0x34))), Some(atom(0x3412)));
+        assert_eq!(jets::cat(&cell!(atom(0), atom(1), atom(1))), Some(atom(3)));
+        assert_eq!(jets::mug(&atom(0)), Some(atom(2046756072)));
+    }
+
+    #[test]
+    fn test_find() {
+        let mut registry = Jets::with_defaults();
+        let battery = cell!(atom(0), atom(6));
+        let core = cell!(battery.clone(), atom(42), atom(0));
+        assert!(registry.find(&core, &Atom::Direct(2)).is_none());
+        // %dec
+        registry.fast(&cell!(atom(0x636564), atom(0)), &core);
+        assert_eq!(registry.find(&core, &Atom::Direct(2)).unwrap().0, "dec");
+        assert!(registry.find(&core, &Atom::Direct(3)).is_none());
+        registry.name_battery(battery, "nope");
+        assert!(registry.find(&core, &Atom::Direct(2)).is_none());
+    }
+}
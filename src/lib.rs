@@ -0,0 +1,42 @@
+//! nock is a library for parsing and evaluating nock expressions.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+extern crate num_bigint;
+extern crate num_traits;
+extern crate rustyline;
+
+#[macro_use]
+mod macros;
+
+pub mod atom;
+pub mod tokenizer;
+pub mod parser;
+pub mod arena;
+pub mod mug;
+pub mod jam;
+pub mod order;
+pub mod convert;
+pub mod pretty;
+pub mod profile;
+pub mod aura;
+pub mod bail;
+pub mod crash;
+pub mod errors;
+pub mod jets;
+pub mod memo;
+pub mod scry;
+pub mod trace;
+pub mod vm;
+pub mod debug;
+pub mod nock;
@@ -13,38 +13,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 extern crate clap;
+extern crate ctrlc;
 extern crate rustyline;
+extern crate nock as libnock;
 
-#[macro_use]
-mod macros;
-
-mod tokenizer;
-mod parser;
-mod errors;
-mod nock;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use clap::{App, Arg};
 use rustyline::Editor;
+use rustyline::error::ReadlineError;
 
-use errors::WrappedError;
+use libnock::{aura, bail, debug, jam, jets, nock, parser, pretty, profile, scry, trace, tokenizer};
+use libnock::parser::Noun;
+use libnock::errors::WrappedError;
 
+// PromptingLineParser reads expressions from the terminal. A line starting
+// with : at the prompt is a repl command and is passed to on_command instead
+// of the parser along with the editor so commands can prompt for more input.
 struct PromptingLineParser {
     read_prompt: String,
     continue_prompt: String,
     is_complete: fn(&Vec<String>) -> bool,
+    on_command: Box<FnMut(&str, &mut Editor<()>)>,
     editor: Editor<()>,
+    interrupt: Arc<AtomicBool>,
 }
 
 impl PromptingLineParser {
     fn new(read_prompt: String,
            continue_prompt: String,
-           is_complete: fn(&Vec<String>) -> bool)
+           is_complete: fn(&Vec<String>) -> bool,
+           on_command: Box<FnMut(&str, &mut Editor<()>)>,
+           interrupt: Arc<AtomicBool>)
            -> PromptingLineParser {
         PromptingLineParser {
             read_prompt: read_prompt,
             continue_prompt: continue_prompt,
             is_complete: is_complete,
+            on_command: on_command,
             editor: Editor::<()>::new(),
+            interrupt: interrupt,
         }
     }
 }
@@ -54,7 +65,23 @@ impl tokenizer::ExpressionReader for PromptingLineParser {
         let mut buffer = Vec::new();
         let mut prompt = &self.read_prompt;
         loop {
-            let line = try!(self.editor.readline(prompt));
+            let line = match self.editor.readline(prompt) {
+                Ok(line) => line,
+                // Ctrl-C at the prompt drops the expression being typed and
+                // prompts again instead of ending the session.
+                Err(ReadlineError::Interrupted) => {
+                    self.interrupt.store(false, Ordering::SeqCst);
+                    buffer.clear();
+                    prompt = &self.read_prompt;
+                    continue;
+                }
+                Err(err) => return Err(WrappedError::from(err)),
+            };
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                self.editor.add_history_entry(&line);
+                (self.on_command)(line.trim(), &mut self.editor);
+                continue;
+            }
             buffer.push(line);
             if (self.is_complete)(&buffer) {
                 break;
@@ -70,6 +97,9 @@ impl tokenizer::ExpressionReader for PromptingLineParser {
 use std::fs::File;
 use std::io::BufReader;
 use std::io::BufRead;
+use std::io::{Read, Write};
+use std::process;
+use std::str::FromStr;
 
 struct FileExpressionReader {
     name: String,
@@ -116,6 +146,26 @@ impl tokenizer::ExpressionReader for FileExpressionReader {
     }
 }
 
+// StringExpressionReader reads a single expression from a string.
+struct StringExpressionReader {
+    expr: Option<String>,
+}
+
+impl StringExpressionReader {
+    fn new<S: Into<String>>(expr: S) -> StringExpressionReader {
+        StringExpressionReader { expr: Some(expr.into()) }
+    }
+}
+
+impl tokenizer::ExpressionReader for StringExpressionReader {
+    fn read(&mut self) -> Result<Vec<String>, WrappedError> {
+        match self.expr.take() {
+            Some(expr) => Ok(vec![expr]),
+            None => Err(WrappedError::new("End of Expression")),
+        }
+    }
+}
+
 fn do_flags<'a>() -> clap::ArgMatches<'a> {
     return App::new("nock")
         .version("0.1")
@@ -127,6 +177,93 @@ fn do_flags<'a>() -> clap::ArgMatches<'a> {
             .value_name("FILE")
             .help("Execute the nock file.")
             .takes_value(true))
+        .arg(Arg::with_name("jam")
+            .short("j")
+            .long("jam")
+            .value_name("FILE")
+            .help("Execute the jammed noun in FILE.")
+            .takes_value(true)
+            .conflicts_with("file"))
+        .arg(Arg::with_name("out")
+            .short("o")
+            .long("out")
+            .value_name("FILE")
+            .help("Write the result to FILE as a jammed noun. A file of more than one expression is rejected. The repl writes its latest result.")
+            .takes_value(true))
+        .arg(Arg::with_name("width")
+            .short("w")
+            .long("width")
+            .value_name("COLUMNS")
+            .help("Break printed results wider than COLUMNS. Defaults to 80.")
+            .takes_value(true))
+        .arg(Arg::with_name("depth")
+            .short("d")
+            .long("depth")
+            .value_name("DEPTH")
+            .help("Elide cells nested deeper than DEPTH in printed results.")
+            .takes_value(true))
+        .arg(Arg::with_name("aura")
+            .short("a")
+            .long("aura")
+            .value_name("AURA")
+            .help("Print atoms as one of @ud @ux @ub @t @tas @p @da or auto to guess.")
+            .takes_value(true))
+        .arg(Arg::with_name("spec")
+            .short("s")
+            .long("spec")
+            .value_name("VERSION")
+            .help("Evaluate with nock 4k or the legacy 5k rules. Defaults to 4k.")
+            .takes_value(true))
+        .arg(Arg::with_name("mode")
+            .long("mode")
+            .value_name("MODE")
+            .help("Compute with the bytecode VM, the tree walking reference or check one against the other. Defaults to tree.")
+            .takes_value(true))
+        .arg(Arg::with_name("scry")
+            .long("scry")
+            .value_name("DIR")
+            .help("Answer nock 12 reads of /foo/bar from the jammed file DIR/foo/bar.jam.")
+            .takes_value(true))
+        .arg(Arg::with_name("max-steps")
+            .long("max-steps")
+            .value_name("STEPS")
+            .help("Crash computations that take more than STEPS steps and print the steps each result took.")
+            .takes_value(true))
+        .arg(Arg::with_name("trace")
+            .short("t")
+            .long("trace")
+            .help("Print each reduction step of every computation."))
+        .arg(Arg::with_name("trace-depth")
+            .long("trace-depth")
+            .value_name("DEPTH")
+            .help("Only trace computations nested DEPTH or fewer deep.")
+            .takes_value(true)
+            .requires("trace"))
+        .arg(Arg::with_name("trace-elide")
+            .long("trace-elide")
+            .value_name("DEPTH")
+            .help("Elide cells nested deeper than DEPTH in traced subjects, formulas and products.")
+            .takes_value(true)
+            .requires("trace"))
+        .arg(Arg::with_name("memo-size")
+            .long("memo-size")
+            .value_name("ENTRIES")
+            .help("Cache at most ENTRIES products of %memo hinted formulas. Defaults to 65536.")
+            .takes_value(true))
+        .arg(Arg::with_name("no-jets")
+            .long("no-jets")
+            .help("Run the nock of well known gates instead of their jets."))
+        .arg(Arg::with_name("verify-jets")
+            .long("verify-jets")
+            .help("Run the nock of jetted gates too and report jets that disagree with it.")
+            .conflicts_with("no-jets"))
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .help("Profile every computation and print a report when the file finishes."))
+        .arg(Arg::with_name("mug")
+            .short("m")
+            .long("mug")
+            .help("Print the mug of each result."))
         .get_matches();
 }
 
@@ -145,31 +282,316 @@ fn is_complete_expr(lines: &Vec<String>) -> bool {
     return count == 0;
 }
 
+// Settings controls how results are reported.
+struct Settings {
+    interpreter: nock::Interpreter,
+    limits: nock::Limits,
+    printer: pretty::Printer,
+    print_mug: bool,
+    out: Option<String>,
+}
+
+// run_command runs a repl command like :aura @ux.
+fn run_command(line: &str, settings: &mut Settings, editor: &mut Editor<()>) {
+    let mut words = line[1..].split_whitespace();
+    match words.next() {
+        Some("debug") => {
+            let expr = line[1..].trim_start()["debug".len()..].trim();
+            debug_expr(expr, settings, editor);
+        }
+        Some("aura") => {
+            match words.next() {
+                None => {
+                    match settings.printer.aura() {
+                        Some(a) => println!("{}", a),
+                        None => println!("off"),
+                    }
+                }
+                Some("off") => settings.printer.set_aura(None),
+                Some(name) => {
+                    match aura::Aura::from_str(name) {
+                        Ok(a) => settings.printer.set_aura(Some(a)),
+                        Err(err) => println!("{}", err),
+                    }
+                }
+            }
+        }
+        Some("profile") => {
+            match words.next() {
+                None => print_profile(settings),
+                Some("on") => settings.interpreter.set_profile(Some(profile::Profile::new())),
+                Some("off") => settings.interpreter.set_profile(None),
+                Some(arg) => println!("Unknown profile command {}. Try :profile [on|off]", arg),
+            }
+        }
+        Some("memo") => {
+            match words.next() {
+                None => println!("memo: {}", settings.interpreter.memo().stats()),
+                Some("clear") => settings.interpreter.memo().clear(),
+                Some(arg) => println!("Unknown memo command {}. Try :memo or :memo clear", arg),
+            }
+        }
+        _ => {
+            println!("Unknown command {}. Try :aura [AURA|off], :debug EXPR, :memo [clear] or \
+                      :profile [on|off]",
+                     line)
+        }
+    }
+}
+
+// The number of formulas a profile report lists.
+const PROFILE_TOP: usize = 10;
+
+// print_profile prints the profile recorded since profiling started or the
+// last report and starts a new one.
+fn print_profile(settings: &mut Settings) {
+    match settings.interpreter.profile() {
+        Some(profile) => {
+            // Formulas can be huge so they are printed on one line without
+            // their deep cells.
+            let printer = pretty::Printer::new(std::usize::MAX, Some(4));
+            print!("{}", profile.report(PROFILE_TOP, &printer));
+        }
+        None => {
+            println!("Profiling is off. Try :profile on");
+            return;
+        }
+    }
+    settings.interpreter.set_profile(Some(profile::Profile::new()));
+}
+
+// debug_expr steps through the computation of expr with debugger commands
+// read from the editor until it stops.
+fn debug_expr(expr: &str, settings: &mut Settings, editor: &mut Editor<()>) {
+    let reader = StringExpressionReader::new(expr);
+    let noun = match parser::Parser::new(Box::new(reader)).parse() {
+        Ok(noun) => noun,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let mut debugger = debug::Debugger::new(&mut settings.interpreter,
+                                            noun,
+                                            settings.limits,
+                                            settings.printer.clone());
+    println!("Type help for the debugger commands.");
+    println!("{}", debugger.position());
+    while !debugger.is_stopped() {
+        let line = match editor.readline("debug> ") {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        editor.add_history_entry(&line);
+        println!("{}", debugger.command(&mut settings.interpreter, &line));
+    }
+}
+
+// compute computes the expression with the interpreter and limits in settings
+// and reports any jet mismatches it found.
+fn compute(expr: Noun, settings: &mut Settings) -> Result<nock::Computed, nock::NockError> {
+    let result = settings.interpreter.compute_with_limits(expr, settings.limits);
+    for m in settings.interpreter.jets().take_mismatches() {
+        println!("jet mismatch in {}: sample {} jet {} nock {}",
+                 m.name,
+                 settings.printer.render(&m.sample),
+                 settings.printer.render(&m.jet),
+                 settings.printer.render(&m.nock));
+    }
+    result
+}
+
+// print_result prints the result and returns the exit code for it.
+fn print_result(result: Result<nock::Computed, nock::NockError>, settings: &Settings) -> i32 {
+    match result {
+        Ok(computed) => {
+            let noun = computed.product;
+            println!("{}", settings.printer.render(&noun));
+            if settings.limits.max_steps.is_some() {
+                println!("steps: {}", computed.steps);
+            }
+            if settings.print_mug {
+                println!("mug: {}", noun.mug());
+            }
+            if let Some(ref out) = settings.out {
+                // In the repl the file holds the most recent result.
+                let written = File::create(out).and_then(|mut f| f.write_all(&jam::jam_bytes(&noun)));
+                if let Err(err) = written {
+                    println!("Failed to write {}: {}", out, err);
+                }
+            }
+            0
+        }
+        Err(err) => {
+            println!("{}", err);
+            err.exit_code()
+        }
+    }
+}
+
+// eval_exprs computes every expression and returns the exit code of the last
+// one that crashed.
+fn eval_exprs(mut nock_parser: parser::Parser, settings: &mut Settings) -> i32 {
+    if settings.out.is_some() {
+        // --out holds one jammed noun so a second result would silently
+        // replace the first. Reject the file before computing any of it.
+        let mut exprs = Vec::new();
+        while let Ok(expr) = nock_parser.parse() {
+            exprs.push(expr);
+        }
+        if exprs.len() > 1 {
+            let bail = bail::Bail::Fail(format!("!! --out holds one result but there are {} expressions",
+                                                exprs.len()));
+            eprintln!("{}", bail);
+            return bail.exit_code();
+        }
+        return match exprs.pop() {
+            Some(expr) => {
+                let result = compute(expr, settings);
+                print_result(result, settings)
+            }
+            None => 0,
+        };
+    }
+    let mut code = 0;
+    while let Ok(expr) = nock_parser.parse() {
+        let result = compute(expr, settings);
+        let result_code = print_result(result, settings);
+        if result_code != 0 {
+            code = result_code;
+        }
+    }
+    code
+}
+
+fn usize_flag(matches: &clap::ArgMatches, name: &str) -> Option<usize> {
+    matches.value_of(name).map(|v| {
+        usize::from_str(v).unwrap_or_else(|_| {
+            println!("--{} expects a number not {}", name, v);
+            process::exit(1)
+        })
+    })
+}
+
 fn main() {
     let matches = do_flags();
-    fn eval_exprs(mut nock_parser: parser::Parser) {
-        while let Ok(expr) = nock_parser.parse() {
-            match nock::compute(expr) {
-                Ok(noun) => println!("{}", noun),
-                Err(err) => println!("{}", err),
+    let spec = matches.value_of("spec").map_or(nock::Spec::default(), |v| {
+        nock::Spec::from_str(v).unwrap_or_else(|err| {
+            println!("{}", err);
+            process::exit(1)
+        })
+    });
+    let mode = matches.value_of("mode").map_or(nock::Mode::Tree, |v| {
+        nock::Mode::from_str(v).unwrap_or_else(|err| {
+            println!("{}", err);
+            process::exit(1)
+        })
+    });
+    let mut interpreter = nock::Interpreter::new(spec);
+    interpreter.set_mode(mode);
+    if !matches.is_present("no-jets") {
+        let mut jets = jets::Jets::with_defaults();
+        jets.set_verify(matches.is_present("verify-jets"));
+        interpreter.set_jets(jets);
+    }
+    if matches.is_present("profile") {
+        interpreter.set_profile(Some(profile::Profile::new()));
+    }
+    if let Some(size) = usize_flag(&matches, "memo-size") {
+        interpreter.memo().set_capacity(size);
+    }
+    if let Some(dir) = matches.value_of("scry") {
+        interpreter.set_namespace(Box::new(scry::JamDir::new(dir)));
+    }
+    if matches.is_present("trace") {
+        let tracer = trace::TraceWriter::new(std::io::stdout(),
+                                             usize_flag(&matches, "trace-depth"),
+                                             usize_flag(&matches, "trace-elide"));
+        interpreter.set_tracer(Some(Box::new(tracer)));
+    }
+    let mut settings = Settings {
+        interpreter: interpreter,
+        limits: nock::Limits { max_steps: usize_flag(&matches, "max-steps").map(|n| n as u64) },
+        printer: pretty::Printer::new(usize_flag(&matches, "width").unwrap_or(80),
+                                      usize_flag(&matches, "depth")),
+        print_mug: matches.is_present("mug"),
+        out: matches.value_of("out").map(|s| s.to_string()),
+    };
+    if let Some(name) = matches.value_of("aura") {
+        match aura::Aura::from_str(name) {
+            Ok(a) => settings.printer.set_aura(Some(a)),
+            Err(err) => {
+                println!("{}", err);
+                process::exit(1)
             }
         }
     }
 
-    if let Some(filename) = matches.value_of("file") {
+    if let Some(filename) = matches.value_of("jam") {
+        // cue and execute a single jammed noun.
+        let mut bytes = Vec::new();
+        let mut file = File::open(filename).expect("Failed to open file!");
+        file.read_to_end(&mut bytes).expect("Failed to read file!");
+        match jam::cue_bytes(&bytes) {
+            Ok(expr) => {
+                let result = compute(expr, &mut settings);
+                let code = print_result(result, &settings);
+                if matches.is_present("profile") {
+                    print_profile(&mut settings);
+                }
+                process::exit(code)
+            }
+            Err(err) => {
+                // A malformed jam crashes like any other malformed input.
+                let bail = bail::Bail::Fail(err.to_string());
+                eprintln!("{}", bail);
+                process::exit(bail.exit_code())
+            }
+        }
+    } else if let Some(filename) = matches.value_of("file") {
         // parse and execute file stream.
         let mut reader = FileExpressionReader::new(filename, is_complete_expr);
         reader.open().expect("Failed to open file!");
         let nock_parser = parser::Parser::new(Box::new(reader));
-        eval_exprs(nock_parser);
+        let code = eval_exprs(nock_parser, &mut settings);
+        if matches.is_present("profile") {
+            print_profile(&mut settings);
+        }
+        process::exit(code);
     } else {
         // parse and execute stdin.
         println!("Welcome to the nock repl!");
         println!("Type nock expressions at the prompt.");
+        println!("Type :aura AURA to change how atoms are printed.");
+        println!("Type :debug EXPR to step through a computation.");
+        println!("Type :profile to see where computations spent their steps.");
+        println!("Ctrl-C interrupts a running computation or clears the prompt.");
         println!("Ctrl-D to quit...\n");
-        let reader =
-            PromptingLineParser::new("nock> ".to_string(), ">     ".to_string(), is_complete_expr);
-        let nock_parser = parser::Parser::new(Box::new(reader));
-        eval_exprs(nock_parser);
+        // Ctrl-C while a computation runs interrupts it instead of killing
+        // the repl.
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let handler_interrupt = interrupt.clone();
+        ctrlc::set_handler(move || handler_interrupt.store(true, Ordering::SeqCst))
+            .expect("Failed to install the Ctrl-C handler!");
+        settings.interpreter.set_interrupt(interrupt.clone());
+        // The reader runs commands between expressions so it shares the
+        // settings with the loop printing results.
+        let settings = Rc::new(RefCell::new(settings));
+        let command_settings = settings.clone();
+        let reader = PromptingLineParser::new("nock> ".to_string(),
+                                              ">     ".to_string(),
+                                              is_complete_expr,
+                                              Box::new(move |line, editor| {
+                                                  run_command(line,
+                                                              &mut command_settings.borrow_mut(),
+                                                              editor)
+                                              }),
+                                              interrupt);
+        let mut nock_parser = parser::Parser::new(Box::new(reader));
+        while let Ok(expr) = nock_parser.parse() {
+            let mut settings = settings.borrow_mut();
+            let result = compute(expr, &mut settings);
+            print_result(result, &settings);
+        }
     }
 }
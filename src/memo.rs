@@ -0,0 +1,177 @@
+//! memo implements the cache behind %memo hints.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use parser::Noun;
+
+/// DEFAULT_MEMO_CAPACITY is the default number of products a Memo holds.
+pub const DEFAULT_MEMO_CAPACITY: usize = 1 << 16;
+
+/// Memo caches the products of formulas computed under a %memo hint:
+///
+/// ```text
+/// *[a 11 %memo c]
+/// *[a 11 [%memo b] c]
+/// ```
+///
+/// Products are keyed on the subject a and formula c. Once the cache is full
+/// the oldest product is forgotten to make room for a new one.
+pub struct Memo {
+    products: HashMap<Noun, Noun>,
+    order: VecDeque<Noun>,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+}
+
+/// MemoStats is a snapshot of a Memo's size and how often it was used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl fmt::Display for MemoStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} of {} entries, {} hits, {} misses",
+               self.entries,
+               self.capacity,
+               self.hits,
+               self.misses)
+    }
+}
+
+impl Memo {
+    /// new constructs an empty Memo holding at most capacity products. A
+    /// capacity of 0 caches nothing.
+    pub fn new(capacity: usize) -> Self {
+        Memo {
+            products: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// set_capacity sets the number of products the Memo holds forgetting
+    /// the oldest ones if it holds more.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// get returns the cached product of formula against subj and counts
+    /// the hit or miss.
+    pub fn get(&mut self, subj: &Noun, formula: &Noun) -> Option<Noun> {
+        let found = self.products.get(&Noun::cons(subj.clone(), formula.clone())).cloned();
+        match found {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        found
+    }
+
+    /// insert caches the product of formula against subj.
+    pub fn insert(&mut self, subj: Noun, formula: Noun, product: Noun) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = Noun::cons(subj, formula);
+        if self.products.insert(key.clone(), product).is_none() {
+            self.order.push_back(key);
+            self.evict();
+        }
+    }
+
+    /// clear forgets every product and resets the statistics.
+    pub fn clear(&mut self) {
+        self.products.clear();
+        self.order.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// stats returns the Memo's size and hit and miss counts.
+    pub fn stats(&self) -> MemoStats {
+        MemoStats {
+            entries: self.products.len(),
+            capacity: self.capacity,
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.order.len() > self.capacity {
+            if let Some(key) = self.order.pop_front() {
+                self.products.remove(&key);
+            }
+        }
+    }
+}
+
+impl Default for Memo {
+    fn default() -> Self {
+        Memo::new(DEFAULT_MEMO_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod memo_tests {
+    use memo::{Memo, MemoStats};
+    use parser::atom;
+
+    #[test]
+    fn test_memo() {
+        let mut memo = Memo::new(2);
+        assert_eq!(memo.get(&atom(1), &atom(2)), None);
+        memo.insert(atom(1), atom(2), atom(3));
+        assert_eq!(memo.get(&atom(1), &atom(2)), Some(atom(3)));
+        assert_eq!(memo.get(&atom(2), &atom(1)), None);
+        assert_eq!(memo.stats(),
+                   MemoStats {
+                       entries: 1,
+                       capacity: 2,
+                       hits: 1,
+                       misses: 2,
+                   });
+        memo.clear();
+        assert_eq!(memo.get(&atom(1), &atom(2)), None);
+        assert_eq!(memo.stats().misses, 1);
+        assert_eq!(memo.stats().to_string(), "0 of 2 entries, 0 hits, 1 misses");
+    }
+
+    #[test]
+    fn test_memo_bounded() {
+        let mut memo = Memo::new(2);
+        memo.insert(atom(1), atom(0), atom(10));
+        memo.insert(atom(2), atom(0), atom(20));
+        memo.insert(atom(3), atom(0), atom(30));
+        assert_eq!(memo.stats().entries, 2);
+        assert_eq!(memo.get(&atom(1), &atom(0)), None);
+        assert_eq!(memo.get(&atom(3), &atom(0)), Some(atom(30)));
+        memo.set_capacity(1);
+        assert_eq!(memo.get(&atom(2), &atom(0)), None);
+        assert_eq!(memo.get(&atom(3), &atom(0)), Some(atom(30)));
+        memo.set_capacity(0);
+        memo.insert(atom(1), atom(0), atom(10));
+        assert_eq!(memo.stats().entries, 0);
+    }
+}
@@ -0,0 +1,188 @@
+//! mug implements Urbit compatible noun hashing.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::hash::{Hash, Hasher};
+
+use atom::Atom;
+use parser::Noun;
+
+/// murmur3_32 is the 32 bit x86 variant of MurmurHash3.
+///
+/// This is the hash Urbit calls muk.
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    let c1 = 0xcc9e2d51u32;
+    let c2 = 0x1b873593u32;
+    let mut h = seed;
+    let blocks = data.len() / 4;
+    for i in 0..blocks {
+        let b = &data[i * 4..i * 4 + 4];
+        let mut k = (b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 |
+                    (b[3] as u32) << 24;
+        k = k.wrapping_mul(c1);
+        k = k.rotate_left(15);
+        k = k.wrapping_mul(c2);
+        h ^= k;
+        h = h.rotate_left(13);
+        h = h.wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    let tail = &data[blocks * 4..];
+    let mut k = 0u32;
+    if tail.len() >= 3 {
+        k ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        k ^= (tail[1] as u32) << 8;
+    }
+    if tail.len() >= 1 {
+        k ^= tail[0] as u32;
+        k = k.wrapping_mul(c1);
+        k = k.rotate_left(15);
+        k = k.wrapping_mul(c2);
+        h ^= k;
+    }
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^= h >> 16;
+    return h;
+}
+
+// mum folds a murmur3 hash into 31 bits retrying with the next seed when the
+// result is 0. After 8 attempts it gives up and returns the fallback.
+fn mum(seed: u32, fallback: u32, bytes: &[u8]) -> u32 {
+    let mut seed = seed;
+    for _ in 0..8 {
+        let haz = murmur3_32(bytes, seed);
+        let ham = (haz >> 31) ^ (haz & 0x7fffffff);
+        if ham != 0 {
+            return ham;
+        }
+        seed = seed.wrapping_add(1);
+    }
+    return fallback;
+}
+
+/// mug_atom computes the mug of an Atom.
+pub fn mug_atom(a: &Atom) -> u32 {
+    mum(0xcafebabe, 0x7fff, &a.to_bytes_le())
+}
+
+/// mug_both computes the mug of a cell from the mugs of its head and tail.
+pub fn mug_both(head: u32, tail: u32) -> u32 {
+    // (cat 5 head tail) as bytes without the trailing zeros.
+    let mut bytes = Vec::with_capacity(8);
+    let both = (head as u64) | (tail as u64) << 32;
+    let mut next = both;
+    while next > 0 {
+        bytes.push((next & 0xff) as u8);
+        next = next >> 8;
+    }
+    mum(0xdeadbeef, 0xfffe, &bytes)
+}
+
+impl Noun {
+    /// mug returns the 31 bit Urbit hash of the Noun.
+    ///
+    /// Like Vere the mug of a Cell is computed once and cached in the Cell.
+    /// Since the mug of a Cell is built from the mugs of its head and tail a
+    /// shared subtree is only ever hashed once.
+    pub fn mug(&self) -> u32 {
+        if let Some(mug) = self.shallow_mug() {
+            return mug;
+        }
+        // Fill in the cache bottom up with an explicit stack so deep nouns
+        // can't overflow the native stack.
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((noun, ready)) = stack.pop() {
+            if let Noun::Cell(ref cell) = noun {
+                if cell.cached_mug().is_some() {
+                    continue;
+                }
+                if ready {
+                    let mug = mug_both(cell.head().shallow_mug().unwrap(),
+                                       cell.tail().shallow_mug().unwrap());
+                    cell.cache_mug(mug);
+                    continue;
+                }
+                stack.push((noun.clone(), true));
+                stack.push((cell.head().clone(), false));
+                stack.push((cell.tail().clone(), false));
+            }
+        }
+        self.shallow_mug().unwrap()
+    }
+
+    // shallow_mug returns the mug of an Atom or the cached mug of a Cell.
+    fn shallow_mug(&self) -> Option<u32> {
+        match self {
+            &Noun::Atom(ref a) => Some(mug_atom(a)),
+            &Noun::Cell(ref cell) => cell.cached_mug(),
+        }
+    }
+}
+
+// Nouns hash by mug so a shared or cached subtree is never rehashed.
+impl Hash for Noun {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u32(self.mug());
+    }
+}
+
+#[cfg(test)]
+mod mug_tests {
+    use std::str::FromStr;
+    use atom::Atom;
+    use mug::{murmur3_32, mug_both};
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_murmur3_vectors() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"", 1), 0x514e28b7);
+        assert_eq!(murmur3_32(b"hello", 0), 0x248bfa47);
+        assert_eq!(murmur3_32(b"Hello, world!", 1234), 0xfaf6cdb3);
+    }
+
+    #[test]
+    fn test_mug_atom() {
+        // (mug 0) in hoon.
+        assert_eq!(atom(0).mug(), 2046756072);
+        assert_eq!(atom(1).mug(), 1901865568);
+        let big = Noun::Atom(Atom::from_str("340282366920938463463374607431768211456").unwrap());
+        assert!(big.mug() < 0x80000000);
+        assert!(big.mug() != 0);
+    }
+
+    #[test]
+    fn test_mug_cell_is_cached() {
+        let noun = cell!(atom(1), cell!(atom(2), atom(3)));
+        let expected = mug_both(atom(1).mug(), mug_both(atom(2).mug(), atom(3).mug()));
+        assert_eq!(noun.mug(), expected);
+        // A second call is served from the cache and agrees.
+        assert_eq!(noun.mug(), expected);
+        assert_eq!(noun.mug(), cell!(atom(1), atom(2), atom(3)).mug());
+        assert!(noun.mug() != cell!(atom(1), atom(3), atom(2)).mug());
+    }
+
+    #[test]
+    fn test_mug_deep_noun() {
+        let mut list = atom(0);
+        for i in 0..100000 {
+            list = Noun::cons(atom(i), list);
+        }
+        assert!(list.mug() != 0);
+    }
+}
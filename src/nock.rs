@@ -12,30 +12,120 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use atom::Atom;
+use bail::{Bail, Exit};
 use parser::{Noun, ParseError, atom};
+use crash::{self, Hint};
+use jets::{Jets, Mismatch};
+use memo::Memo;
+use profile::Profile;
+use scry::{Blocking, Namespace};
+use trace::Tracer;
+use vm::{self, Code};
 use std::error;
 use std::fmt;
 use std::fmt::Display;
-use std::collections::VecDeque;
+use std::mem;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
-make_error!(NockError, "NockError: {}\n");
+/// NockError is a crashed computation. It holds the Bail that classifies
+/// the crash and the trace stack the computation crashed under.
+#[derive(Debug)]
+pub struct NockError {
+    bail: Bail,
+    trace: Vec<String>,
+}
 
-impl From<ParseError> for NockError {
-    fn from(err: ParseError) -> Self {
-        Self::new_with_cause("AST Parse Error", Box::new(err))
+impl NockError {
+    /// new constructs a NockError for bail with an empty trace.
+    pub fn new(bail: Bail) -> Self {
+        NockError {
+            bail: bail,
+            trace: Vec::new(),
+        }
+    }
+
+    /// bail returns why the computation crashed.
+    pub fn bail(&self) -> &Bail {
+        &self.bail
+    }
+
+    /// trace returns the rendered trace stack from the outermost entry to
+    /// the innermost one.
+    pub fn trace(&self) -> &[String] {
+        &self.trace
+    }
+
+    /// exit_code returns the process exit code for the crash.
+    pub fn exit_code(&self) -> i32 {
+        self.bail.exit_code()
+    }
+
+    /// is_out_of_fuel returns true if the computation crashed because it
+    /// ran out of steps.
+    pub fn is_out_of_fuel(&self) -> bool {
+        match self.bail {
+            Bail::Fuel { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// is_interrupted returns true if the computation was interrupted.
+    pub fn is_interrupted(&self) -> bool {
+        match self.bail {
+            Bail::Intr { .. } => true,
+            _ => false,
+        }
     }
 }
 
-fn slice_to_noun(nouns: &[Noun]) -> Result<Noun, NockError> {
-    if nouns.len() > 1 {
-        Ok(Noun::Cell(nouns.iter().cloned().collect()))
-    } else if nouns.len() == 1 {
-        Ok(nouns[0].clone())
-    } else {
-        Err(NockError::new("!! Nock Empty Cell"))
+impl Display for NockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        try!(write!(f, "NockError: {}", self.bail));
+        for line in self.trace.iter() {
+            try!(write!(f, "\n{}", line));
+        }
+        write!(f, "\n")
     }
 }
 
+impl error::Error for NockError {
+    fn description(&self) -> &str {
+        self.bail.name()
+    }
+}
+
+impl From<Bail> for NockError {
+    fn from(bail: Bail) -> Self {
+        Self::new(bail)
+    }
+}
+
+impl From<Exit> for NockError {
+    fn from(exit: Exit) -> Self {
+        Self::new(Bail::Exit(exit))
+    }
+}
+
+impl From<ParseError> for NockError {
+    fn from(err: ParseError) -> Self {
+        Self::new(Bail::Fail(err.to_string().trim_end().to_string()))
+    }
+}
+
+// The %fast hint tag.
+pub(crate) const FAST: u64 = 0x74736166;
+// The %memo hint tag.
+pub(crate) const MEMO: u64 = 0x6f6d656d;
+
+// The most steps a %mean trap can take to render when a computation crashes.
+const MEAN_STEPS: u64 = 100000;
+
 /// # Algorithm
 /// Nock calculates tree addresses using an algorithm like so:
 /// * 1 is the root of the tree.
@@ -60,44 +150,112 @@ fn slice_to_noun(nouns: &[Noun]) -> Result<Noun, NockError> {
 /// 3 is the tail of 1
 ///
 /// Which yields a path of [false, true]
-fn make_tree_path(addr: u64) -> Vec<bool> {
-    let mut ret = VecDeque::new();
-    let mut next = addr;
-    loop {
-        ret.push_front(next % 2 == 0);
-        next = next / 2;
-        if next <= 1 {
-            break;
-        }
-    }
-    let range = 0..ret.len();
-    let path = ret.drain(range).collect();
+///
+/// Since an address can be larger than a u64 we walk the bits of the
+/// address instead of dividing. The bits below the most significant
+/// bit are exactly the head/tail choices read from the root down.
+fn make_tree_path(addr: &Atom) -> Vec<bool> {
+    if let &Atom::Direct(addr) = addr {
+        let mut ret = VecDeque::new();
+        let mut next = addr;
+        while next > 1 {
+            ret.push_front(next % 2 == 0);
+            next = next / 2;
+        }
+        let range = 0..ret.len();
+        let path = ret.drain(range).collect();
+        return path;
+    }
+    let bytes = addr.to_bytes_le();
+    let len = addr.bit_len();
+    let mut path = Vec::with_capacity(len - 1);
+    for i in (0..len - 1).rev() {
+        path.push(bytes[i / 8] & (1 << (i % 8)) == 0);
+    }
     return path;
 }
 
-fn fas(subj: &Noun, addr: u64) -> Result<Noun, NockError> {
-    if addr == 0 {
-        return Err(NockError::new("!! Invalid slot address 0"));
+pub(crate) fn fas(subj: &Noun, addr: &Atom) -> Result<Noun, NockError> {
+    let crash = || {
+        NockError::from(Exit::Slot {
+            axis: addr.clone(),
+            noun: subj.clone(),
+        })
+    };
+    if addr.as_u64() == Some(0) {
+        return Err(crash());
     }
-    if addr == 1 {
-        return Ok(subj.clone());
-    }
-    if addr == 2 {
-        return Ok(try!(subj.head()).clone());
+    let path = make_tree_path(addr);
+    let mut subject = subj;
+    for take_head in path {
+        subject = match subject.as_cell() {
+            Some((head, _)) if take_head => head,
+            Some((_, tail)) => tail,
+            None => return Err(crash()),
+        }
     }
-    if addr == 3 {
-        return slice_to_noun(try!(subj.tail()));
+    Ok(subject.clone())
+}
+
+// hax replaces the noun at addr in target with value. This is the # edit
+// operator:
+//
+// * #[1 a b]           a
+// * #[(a + a) b c]     #[a [b /[(a + a + 1) c]] c]
+// * #[(a + a + 1) b c] #[a [/[(a + a) c] b] c]
+pub(crate) fn hax(addr: &Atom, value: Noun, target: &Noun) -> Result<Noun, NockError> {
+    let crash = || {
+        NockError::from(Exit::Edit {
+            axis: addr.clone(),
+            noun: target.clone(),
+        })
+    };
+    if addr.as_u64() == Some(0) {
+        return Err(crash());
     }
     let path = make_tree_path(addr);
-    let mut subject = subj.clone();
+    // Walk down to the edited axis remembering the sibling at each step so
+    // the cells along the path can be rebuilt around the new value.
+    let mut siblings = Vec::with_capacity(path.len());
+    let mut subject = target;
     for take_head in path {
-        subject = if take_head {
-            try!(subject.head()).clone()
+        let (head, tail) = match subject.as_cell() {
+            Some(pair) => pair,
+            None => return Err(crash()),
+        };
+        if take_head {
+            siblings.push((true, tail));
+            subject = head;
         } else {
-            try!(slice_to_noun(try!(subject.tail())))
+            siblings.push((false, head));
+            subject = tail;
         }
     }
-    Ok(subject)
+    let mut result = value;
+    while let Some((took_head, sibling)) = siblings.pop() {
+        result = if took_head {
+            Noun::cons(result, sibling.clone())
+        } else {
+            Noun::cons(sibling.clone(), result)
+        };
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+#[test]
+fn test_hax() {
+    let target = cell!(atom(531), cell!(atom(25), atom(26)), atom(99));
+    let cases = vec![(1, atom(7)),
+                     (2, cell!(atom(7), cell!(atom(25), atom(26)), atom(99))),
+                     (6, cell!(atom(531), atom(7), atom(99))),
+                     (13, cell!(atom(531), cell!(atom(25), atom(7)), atom(99))),
+                     (7, cell!(atom(531), cell!(atom(25), atom(26)), atom(7)))];
+    for (addr, expected) in cases {
+        assert_eq!(hax(&Atom::Direct(addr), atom(7), &target).unwrap(), expected);
+    }
+    assert!(hax(&Atom::Direct(0), atom(7), &target).is_err());
+    assert!(hax(&Atom::Direct(14), atom(7), &target).is_err());
 }
 
 #[cfg(test)]
@@ -123,7 +281,7 @@ fn test_simple_fas() {
                       atom(26)),
                      ];
     for (subj, addr, expected) in cases {
-        assert_eq!(expected, fas(&subj, addr).unwrap());
+        assert_eq!(expected, fas(&subj, &Atom::Direct(addr)).unwrap());
     }
     // [12 [531 25 99]] crashes
 }
@@ -134,11 +292,24 @@ fn test_simple_fas() {
 fn test_fas_crash() {
     let cell = cell!(atom(531), atom(25), atom(99));
     // We expect an error here so we crash.
-    fas(&cell, 12).unwrap();
+    fas(&cell, &Atom::Direct(12)).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn test_make_tree_path_big_addr() {
+    use std::str::FromStr;
+    // 2^64 is 64 heads down from the root.
+    let addr = Atom::from_str("18446744073709551616").unwrap();
+    assert_eq!(make_tree_path(&addr), vec![true; 64]);
+    // 2^65 - 1 is 64 tails down from the root.
+    let addr = Atom::from_str("36893488147419103231").unwrap();
+    assert_eq!(make_tree_path(&addr), vec![false; 64]);
+    assert_eq!(make_tree_path(&Atom::Direct(6)), vec![false, true]);
 }
 
 // Returns 1 false for an Noun::Atom and 0 true for a Noun::Cell.
-fn wut(noun: Noun) -> Noun {
+pub(crate) fn wut(noun: Noun) -> Noun {
     match noun {
         Noun::Atom(_) => atom(1),
         Noun::Cell(_) => atom(0),
@@ -146,10 +317,10 @@ fn wut(noun: Noun) -> Noun {
 }
 
 // lus increments a Noun::Atom but crashes for a Noun::Cell.
-fn lus(noun: Noun) -> Result<Noun, NockError> {
+pub(crate) fn lus(noun: Noun) -> Result<Noun, NockError> {
     match noun {
-        Noun::Atom(a) => Ok(atom(a + 1)),
-        Noun::Cell(_) => Err(NockError::new("!! Can't increment a cell")),
+        Noun::Atom(a) => Ok(Noun::Atom(a.inc())),
+        cell => Err(NockError::from(Exit::Increment(cell))),
     }
 }
 
@@ -157,6 +328,8 @@ fn lus(noun: Noun) -> Result<Noun, NockError> {
 #[test]
 fn test_lus() {
     assert_eq!(lus(atom(1)).expect("Should be able to increment an atom"), atom(2));
+    assert_eq!(format!("{}", lus(atom(::std::u64::MAX)).unwrap()),
+               "18446744073709551616");
 }
 
 #[cfg(test)]
@@ -166,48 +339,15 @@ fn test_lus_crash() {
     lus(cell!(atom(1), atom(2))).unwrap();
 }
 
-fn cmp_noun(a: &Noun, b: &[Noun]) -> Noun {
-    let truthy = atom(0);
-    let falsy = atom(1);
-    match a {
-        &Noun::Cell(ref list) => {
-            if b.len() == 1 {
-                if let Noun::Cell(ref list) = b[0] {
-                    return cmp_noun(a, &list);
-                }
-            }
-            if list.len() != b.len() {
-                return falsy;
-            }
-            for (i, n) in list.iter().enumerate() {
-                if cmp_noun(n, &b[i..i+1]) == falsy {
-                    return falsy;
-                }
-            }
-            return truthy;
-        }
-        &Noun::Atom(a) => {
-            if b.len() == 1 {
-                if let Noun::Atom(b) = b[0] {
-                    if a == b {
-                        return truthy;
-                    }
-                }
-            }
-            return falsy;
-        }
-    }
-}
-
 // tis compares a Noun::Cell's head and tail Nouns for equality.
-fn tis(noun: Noun) -> Result<Noun, NockError> {
+pub(crate) fn tis(noun: Noun) -> Result<Noun, NockError> {
     match noun {
-        Noun::Atom(_) => Err(NockError::new("!! Can't compaire Atom like a cell")),
-        Noun::Cell(list) => {
-            if list.len() >= 2 {
-                Ok(cmp_noun(&list[0], &list[1..]))
+        Noun::Atom(a) => Err(NockError::from(Exit::Compare(Noun::Atom(a)))),
+        Noun::Cell(cell) => {
+            if cell.head() == cell.tail() {
+                Ok(atom(0))
             } else {
-                Err(NockError::new("!! Can't compare a cell of only one Noun"))
+                Ok(atom(1))
             }
         }
     }
@@ -243,28 +383,665 @@ fn test_tis_crash_atom() {
     tis(atom(1)).unwrap();
 }
 
-#[cfg(test)]
-#[test]
-#[should_panic]
-fn test_tis_crash_cell() {
-    tis(cell!(atom(1))).unwrap();
+/// Spec selects the version of the nock spec formulas are evaluated with.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Spec {
+    /// Nock 4K. 5 compares two computed nouns, 10 is the # edit operator
+    /// and 11 is a hint.
+    Nock4K,
+    /// Nock 5K. 5 compares the head and tail of one computed cell and 10 is
+    /// a hint. There is no 11.
+    Nock5K,
 }
 
-/// compute computes a nock expression of type [subj formula] or atom
-pub fn compute(noun: Noun) -> Result<Noun, NockError> {
-    match &noun {
-        &Noun::Atom(_) => nock_internal(&Noun::Atom(0), noun.clone()),
-        &Noun::Cell(ref list) => {
-            if list.len() >= 2 {
-                nock_internal(try!(noun.head()), try!(slice_to_noun(try!(noun.tail()))))
-            } else {
-                Err(NockError::new("!! Invalid Nock Expression"))
+impl Default for Spec {
+    fn default() -> Self {
+        Spec::Nock4K
+    }
+}
+
+impl FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "4k" => Ok(Spec::Nock4K),
+            "5k" => Ok(Spec::Nock5K),
+            _ => Err(format!("Unknown nock spec {}. Expected 4k or 5k", s)),
+        }
+    }
+}
+
+impl Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Spec::Nock4K => write!(f, "4k"),
+            &Spec::Nock5K => write!(f, "5k"),
+        }
+    }
+}
+
+/// Mode selects how an Interpreter computes formulas.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Mode {
+    /// Walk the formula tree reducing one formula at a time. This is the
+    /// reference the other modes are checked against.
+    Tree,
+    /// Compile formulas to bytecode and run them on a VM.
+    Bytecode,
+    /// Run the VM and check its product against walking the tree.
+    Check,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Tree
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tree" => Ok(Mode::Tree),
+            "bytecode" => Ok(Mode::Bytecode),
+            "check" => Ok(Mode::Check),
+            _ => Err(format!("Unknown mode {}. Expected tree, bytecode or check", s)),
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Mode::Tree => write!(f, "tree"),
+            &Mode::Bytecode => write!(f, "bytecode"),
+            &Mode::Check => write!(f, "check"),
+        }
+    }
+}
+
+// The most compiled formulas an Interpreter keeps before it forgets the
+// oldest.
+const MAX_CODES: usize = 1 << 16;
+
+/// DEFAULT_STACK_LIMIT is the default number of pending sub-computations
+/// allowed before a computation crashes. A frame is a few dozen bytes so
+/// this bounds the stack at a few hundred MB.
+pub const DEFAULT_STACK_LIMIT: usize = 1 << 22;
+
+/// Interpreter evaluates nock expressions with a version of the nock spec,
+/// a namespace for nock 12 to read from, jets for well known gates and a
+/// cache for %memo hints. A new Interpreter has no jets and walks the
+/// formula tree.
+pub struct Interpreter {
+    spec: Spec,
+    mode: Mode,
+    pub(crate) namespace: Box<Namespace>,
+    pub(crate) stack_limit: usize,
+    pub(crate) interrupt: Option<Arc<AtomicBool>>,
+    tracer: Option<Box<Tracer>>,
+    profile: Option<Profile>,
+    pub(crate) jets: Jets,
+    pub(crate) memo: Memo,
+    // Compiled formulas keyed by formula.
+    codes: HashMap<Noun, Rc<Code>>,
+    // The compiled formulas oldest first.
+    code_order: VecDeque<Noun>,
+}
+
+impl Interpreter {
+    /// new constructs an Interpreter for spec whose namespace blocks every
+    /// read.
+    pub fn new(spec: Spec) -> Self {
+        Interpreter {
+            spec: spec,
+            mode: Mode::default(),
+            namespace: Box::new(Blocking),
+            stack_limit: DEFAULT_STACK_LIMIT,
+            interrupt: None,
+            tracer: None,
+            profile: None,
+            jets: Jets::new(),
+            memo: Memo::default(),
+            codes: HashMap::new(),
+            code_order: VecDeque::new(),
+        }
+    }
+
+    /// set_mode sets how formulas are computed. Tracing and profiling
+    /// always walk the formula tree.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// mode returns how formulas are computed.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// set_stack_limit sets the number of pending sub-computations a
+    /// computation can have before it crashes with a stack overflow.
+    pub fn set_stack_limit(&mut self, frames: usize) {
+        self.stack_limit = frames;
+    }
+
+    /// set_interrupt sets a flag the interpreter checks before every step.
+    /// Setting it from another thread or a signal handler crashes the
+    /// running computation with an interrupted error. The interpreter clears
+    /// the flag when a computation starts and when it is interrupted.
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// set_tracer sets the tracer every reduction is reported to. None
+    /// turns tracing off.
+    pub fn set_tracer(&mut self, tracer: Option<Box<Tracer>>) {
+        self.tracer = tracer;
+    }
+
+    /// set_profile sets the profile every reduction and jet call is
+    /// recorded in. None turns profiling off.
+    pub fn set_profile(&mut self, profile: Option<Profile>) {
+        self.profile = profile;
+    }
+
+    /// profile returns the profile recorded so far if profiling is on.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// set_jets sets the jets gates are run with.
+    pub fn set_jets(&mut self, jets: Jets) {
+        self.jets = jets;
+    }
+
+    /// jets returns the jets gates are run with.
+    pub fn jets(&mut self) -> &mut Jets {
+        &mut self.jets
+    }
+
+    /// memo returns the cache of %memo hinted products. It is kept across
+    /// computations.
+    pub fn memo(&mut self) -> &mut Memo {
+        &mut self.memo
+    }
+
+    /// set_namespace sets the namespace nock 12 reads from.
+    pub fn set_namespace(&mut self, namespace: Box<Namespace>) {
+        self.namespace = namespace;
+    }
+
+    /// compute computes a nock expression of type [subj formula] or atom.
+    pub fn compute(&mut self, noun: Noun) -> Result<Noun, NockError> {
+        self.compute_with_limits(noun, Limits::default()).map(|c| c.product)
+    }
+
+    /// compute_with_limits computes a nock expression of type
+    /// [subj formula] or atom within limits.
+    ///
+    /// A step is a reduction when walking the tree and an instruction when
+    /// running bytecode so the same limits go further on the tree.
+    pub fn compute_with_limits(&mut self, noun: Noun, limits: Limits) -> Result<Computed, NockError> {
+        if self.tracer.is_some() || self.profile.is_some() {
+            return self.walk(noun, limits);
+        }
+        match self.mode {
+            Mode::Tree => self.walk(noun, limits),
+            Mode::Bytecode => vm::run(self, noun, limits),
+            Mode::Check => self.check(noun, limits),
+        }
+    }
+
+    // check walks noun's tree and runs it on the VM and crashes with a bug if
+    // they disagree. Running out of fuel or stack isn't a disagreement since
+    // the two count steps and frames differently.
+    fn check(&mut self, noun: Noun, limits: Limits) -> Result<Computed, NockError> {
+        let walked = self.walk(noun.clone(), limits);
+        // The VM only shadows the walk so it caches in a scratch memo and
+        // finds the jets the walk named without naming any or recording
+        // mismatches.
+        let scratch = Memo::new(self.memo.stats().capacity);
+        let memo = mem::replace(&mut self.memo, scratch);
+        self.jets.freeze(true);
+        let compiled = vm::run(self, noun, limits);
+        self.memo = memo;
+        self.jets.freeze(false);
+        if let Err(ref err) = compiled {
+            if err.is_interrupted() {
+                return compiled;
+            }
+        }
+        let exhausted = |result: &Result<Computed, NockError>| match result {
+            &Err(ref err) => {
+                match err.bail {
+                    Bail::Fuel { .. } | Bail::Oom { .. } | Bail::Intr { .. } => true,
+                    _ => false,
+                }
+            }
+            &Ok(_) => false,
+        };
+        if exhausted(&compiled) || exhausted(&walked) {
+            return walked;
+        }
+        let disagreement = match (&compiled, &walked) {
+            (&Ok(ref c), &Ok(ref w)) if c.product != w.product => {
+                Some(format!("The bytecode product {} isn't the tree product {}", c.product, w.product))
+            }
+            (&Ok(_), &Err(ref err)) => Some(format!("The bytecode didn't crash but the tree did: {}", err.bail)),
+            (&Err(ref err), &Ok(_)) => Some(format!("The bytecode crashed but the tree didn't: {}", err.bail)),
+            _ => None,
+        };
+        match disagreement {
+            Some(msg) => Err(NockError::new(Bail::Bug(msg))),
+            None => walked,
+        }
+    }
+
+    // code returns the compiled formula compiling it if it hasn't been.
+    pub(crate) fn code(&mut self, formula: &Noun) -> Rc<Code> {
+        if let Some(code) = self.codes.get(formula) {
+            return code.clone();
+        }
+        if self.codes.len() >= MAX_CODES {
+            // Forget the oldest formula to make room.
+            if let Some(oldest) = self.code_order.pop_front() {
+                self.codes.remove(&oldest);
+            }
+        }
+        let code = Rc::new(vm::compile(formula, self.spec));
+        self.codes.insert(formula.clone(), code.clone());
+        self.code_order.push_back(formula.clone());
+        code
+    }
+
+    // walk computes noun by walking its formula tree.
+    fn walk(&mut self, noun: Noun, limits: Limits) -> Result<Computed, NockError> {
+        let mut execution = self.start(noun, limits);
+        loop {
+            if let Some(computed) = try!(self.advance(&mut execution)) {
+                return Ok(computed);
+            }
+        }
+    }
+
+    /// start begins computing a nock expression of type [subj formula] or
+    /// atom within limits. The returned Execution is paused before its
+    /// first step.
+    pub fn start(&mut self, noun: Noun, limits: Limits) -> Execution {
+        if let Some(ref flag) = self.interrupt {
+            flag.store(false, Ordering::SeqCst);
+        }
+        let (subj, formula) = match noun {
+            Noun::Atom(_) => (atom(0), noun),
+            Noun::Cell(cell) => (cell.head().clone(), cell.tail().clone()),
+        };
+        Execution {
+            limits: limits,
+            stack: Vec::new(),
+            next: Some(Step::Eval(subj, formula)),
+            steps: 0,
+            produced: None,
+        }
+    }
+
+    /// advance runs execution for one step and pauses it before the next
+    /// one. It returns the Computed product once the execution finishes.
+    ///
+    /// An execution that crashed or finished can't be advanced again.
+    pub fn advance(&mut self, execution: &mut Execution) -> Result<Option<Computed>, NockError> {
+        execution.produced = None;
+        loop {
+            let next = match execution.next.take() {
+                Some(next) => next,
+                None => {
+                    return Err(NockError::new(Bail::Bug("The computation has already stopped"
+                        .to_string())))
+                }
+            };
+            let next = match next {
+                Step::Eval(subj, formula) => {
+                    let steps = execution.steps;
+                    if execution.limits.max_steps.map_or(false, |max| steps >= max) {
+                        let err = NockError::new(Bail::Fuel { steps: steps });
+                        return Err(self.crash(execution, err));
+                    }
+                    let interrupted = match self.interrupt {
+                        Some(ref flag) => flag.swap(false, Ordering::SeqCst),
+                        None => false,
+                    };
+                    if interrupted {
+                        let err = NockError::new(Bail::Intr { steps: steps });
+                        return Err(self.crash(execution, err));
+                    }
+                    execution.steps += 1;
+                    if let Some(ref mut tracer) = self.tracer {
+                        tracer.step(execution.stack.len(), &subj, &formula, rule(self.spec, &formula));
+                    }
+                    if let Some(ref mut profile) = self.profile {
+                        profile.step(execution.stack.len(), &formula);
+                    }
+                    match step(self, subj, formula) {
+                        Ok(next) => next,
+                        Err(err) => return Err(self.crash(execution, err)),
+                    }
+                }
+                Step::Push(frame, subj, formula) => {
+                    if execution.stack.len() >= self.stack_limit {
+                        let err = NockError::new(Bail::Oom { frames: execution.stack.len() });
+                        return Err(self.crash(execution, err));
+                    }
+                    execution.stack.push(frame);
+                    Step::Eval(subj, formula)
+                }
+                Step::Done(product) => {
+                    let depth = execution.stack.len();
+                    execution.produced = Some(execution.produced.map_or(depth, |d| d.min(depth)));
+                    if let Some(ref mut tracer) = self.tracer {
+                        tracer.product(execution.stack.len(), &product);
+                    }
+                    match execution.stack.pop() {
+                        Some(frame) => {
+                            match resume(self, frame, product) {
+                                Ok(next) => next,
+                                Err(err) => return Err(self.crash(execution, err)),
+                            }
+                        }
+                        None => {
+                            return Ok(Some(Computed {
+                                product: product,
+                                steps: execution.steps,
+                            }))
+                        }
+                    }
+                }
+            };
+            let paused = match next {
+                Step::Eval(_, _) => true,
+                _ => false,
+            };
+            execution.next = Some(next);
+            if paused {
+                return Ok(None);
             }
         }
     }
 }
 
-/// Evaluates a nock formula against a subj.
+impl Interpreter {
+    // crash adds the trace stack of execution to err. The trace is rendered
+    // under the crash from the outermost entry to the innermost one.
+    fn crash(&self, execution: &Execution, err: NockError) -> NockError {
+        let trace: Vec<(Hint, Noun)> = execution.stack
+            .iter()
+            .filter_map(|frame| match frame {
+                &Frame::Trace(hint, ref clue) => Some((hint, clue.clone())),
+                _ => None,
+            })
+            .collect();
+        self.traced(&trace, err)
+    }
+
+    // traced adds a trace stack ordered from the outermost entry to err.
+    pub(crate) fn traced(&self, trace: &[(Hint, Noun)], err: NockError) -> NockError {
+        if trace.is_empty() {
+            return err;
+        }
+        let spec = self.spec;
+        let lines = crash::render(trace, |trap| {
+            // Like Hoon's mack the trap computes *[trap -.trap].
+            let formula = match trap.as_cell() {
+                Some((battery, _)) => battery.clone(),
+                None => return None,
+            };
+            let limits = Limits { max_steps: Some(MEAN_STEPS) };
+            Interpreter::new(spec)
+                .compute_with_limits(Noun::cons(trap.clone(), formula), limits)
+                .ok()
+                .map(|computed| computed.product)
+        });
+        NockError {
+            bail: err.bail,
+            trace: lines,
+        }
+    }
+}
+
+/// Execution is a computation that is paused between steps.
+///
+/// A debugger can start an Execution with Interpreter::start, advance it
+/// one step at a time with Interpreter::advance and inspect the formula it
+/// will reduce next in between.
+pub struct Execution {
+    limits: Limits,
+    stack: Vec<Frame>,
+    next: Option<Step>,
+    steps: u64,
+    // The shallowest depth a product was computed at by the last advance.
+    produced: Option<usize>,
+}
+
+impl Execution {
+    /// steps returns the number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// depth returns the number of computations waiting on the next step.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// produced returns the shallowest depth a computation produced its
+    /// product at during the last advance or None if none did. A formula
+    /// in tail position replaces its computation at the same depth so a
+    /// computation has finished once a product is produced at its depth.
+    pub fn produced(&self) -> Option<usize> {
+        self.produced
+    }
+
+    /// subject returns the subject of the next step or None if the
+    /// execution has stopped.
+    pub fn subject(&self) -> Option<&Noun> {
+        match self.next {
+            Some(Step::Eval(ref subj, _)) => Some(subj),
+            _ => None,
+        }
+    }
+
+    /// formula returns the formula of the next step or None if the
+    /// execution has stopped.
+    pub fn formula(&self) -> Option<&Noun> {
+        match self.next {
+            Some(Step::Eval(_, ref formula)) => Some(formula),
+            _ => None,
+        }
+    }
+
+    /// axis_path returns the axis of each waiting computation's sub-formula
+    /// within its formula starting from the outermost computation. Following
+    /// the path through a computation's formula finds the sub-formula it is
+    /// waiting on.
+    pub fn axis_path(&self) -> Vec<u64> {
+        self.stack.iter().map(|f| f.axis()).collect()
+    }
+}
+
+/// Limits bounds the resources a computation can use.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct Limits {
+    /// The number of steps a computation can take before it crashes out of
+    /// fuel. None is unbounded.
+    pub max_steps: Option<u64>,
+}
+
+/// Computed is the product of a computation and the number of steps it
+/// took. A step is one formula reduced against a subject.
+#[derive(Debug)]
+pub struct Computed {
+    pub product: Noun,
+    pub steps: u64,
+}
+
+/// compute computes a nock expression of type [subj formula] or atom with
+/// the current nock spec.
+pub fn compute(noun: Noun) -> Result<Noun, NockError> {
+    compute_with_spec(noun, Spec::default())
+}
+
+/// compute_with_spec computes a nock expression of type [subj formula] or
+/// atom with the given version of the nock spec.
+pub fn compute_with_spec(noun: Noun, spec: Spec) -> Result<Noun, NockError> {
+    Interpreter::new(spec).compute(noun)
+}
+
+/// compute_with_limits computes a nock expression of type [subj formula] or
+/// atom with the current nock spec within limits. A computation that takes
+/// more than limits.max_steps steps crashes with an out of fuel error.
+pub fn compute_with_limits(noun: Noun, limits: Limits) -> Result<Computed, NockError> {
+    Interpreter::new(Spec::default()).compute_with_limits(noun, limits)
+}
+
+// args returns the head and tail of a formula's arguments or crashes with
+// the malformed formula.
+fn args<'a>(op: u64, formula: &Noun, tail: &'a Noun) -> Result<(&'a Noun, &'a Noun), NockError> {
+    match tail.as_cell() {
+        Some(pair) => Ok(pair),
+        None => Err(malformed(op, formula)),
+    }
+}
+
+fn malformed(op: u64, formula: &Noun) -> NockError {
+    NockError::from(Exit::Formula {
+        opcode: Some(op),
+        formula: formula.clone(),
+    })
+}
+
+// Frame is a computation waiting on the product of a sub-formula. The
+// evaluator keeps frames on a heap allocated stack instead of recursing so
+// deep computations can't overflow the native stack.
+enum Frame {
+    // Autocons waiting on the head. Holds the subject and the tail formula.
+    ConsHead(Noun, Noun),
+    // Autocons waiting on the tail. Holds the head.
+    ConsTail(Noun),
+    // 2 waiting on the new subject. Holds the subject and formula c.
+    EvalSubject(Noun, Noun),
+    // 2 waiting on the new formula. Holds the new subject.
+    EvalFormula(Noun),
+    Wut,
+    Lus,
+    // 5K tis waiting on the cell to compare.
+    Tis,
+    // 4K tis waiting on the left noun. Holds the subject and formula c.
+    TisLeft(Noun, Noun),
+    // 4K tis waiting on the right noun. Holds the left noun.
+    TisRight(Noun),
+    // 10 waiting on the new value. Holds the axis, subject and formula d.
+    EditValue(Atom, Noun, Noun),
+    // 10 waiting on the edited noun. Holds the axis and new value.
+    EditTarget(Atom, Noun),
+    // 11 waiting on a dynamic hint. Holds the hint tag, subject and
+    // formula d.
+    Hint(Noun, Noun, Noun),
+    // 11 %fast waiting on the core to name. Holds the clue.
+    Fast(Noun),
+    // 11 %spot, %mean, %hunk or %lose waiting on the hinted formula. Holds
+    // the trace entry.
+    Trace(Hint, Noun),
+    // 11 %memo waiting on the product to cache. Holds the subject and
+    // formula.
+    Memo(Noun, Noun),
+    // 6 waiting on the test. Holds the subject and formulas c and d.
+    Branch(Noun, Noun, Noun),
+    // 7 waiting on the new subject. Holds formula c.
+    Compose(Noun),
+    // 8 waiting on the noun to pin. Holds the subject and formula c.
+    Pin(Noun, Noun),
+    // 9 waiting on the core. Holds the arm's axis.
+    Call(Atom),
+    // A jetted gate waiting on the product of its nock. Holds the jet name,
+    // the sample and the jet's product.
+    Verify(String, Noun, Noun),
+    // 12 waiting on the ref. Holds the subject and formula c.
+    ScryRef(Noun, Noun),
+    // 12 waiting on the path. Holds the ref.
+    ScryPath(Noun),
+}
+
+impl Frame {
+    // axis returns the axis of the sub-formula the frame is waiting on within
+    // the formula that pushed it.
+    fn axis(&self) -> u64 {
+        match self {
+            // [b d]
+            &Frame::ConsHead(_, _) => 2,
+            &Frame::ConsTail(_) => 3,
+            // [3 b], [4 b] and 5K [5 b]
+            &Frame::Wut | &Frame::Lus | &Frame::Tis => 3,
+            // [2 b c], [5 b c] and [12 b c]
+            &Frame::EvalSubject(_, _) | &Frame::TisLeft(_, _) | &Frame::ScryRef(_, _) => 6,
+            &Frame::EvalFormula(_) | &Frame::TisRight(_) | &Frame::ScryPath(_) => 7,
+            // [6 b c d], [7 b c] and [8 b c]
+            &Frame::Branch(_, _, _) | &Frame::Compose(_) | &Frame::Pin(_, _) => 6,
+            // [9 b c]
+            &Frame::Call(_) => 7,
+            // [10 [b c] d] and [11 [b c] d]
+            &Frame::EditValue(_, _, _) | &Frame::Hint(_, _, _) => 13,
+            &Frame::EditTarget(_, _) | &Frame::Fast(_) | &Frame::Memo(_, _) => 7,
+            &Frame::Trace(_, _) => 7,
+            // The gate's whole arm.
+            &Frame::Verify(_, _, _) => 1,
+        }
+    }
+}
+
+// Step is what the evaluator does next.
+enum Step {
+    // Hand the product to the frame on top of the stack.
+    Done(Noun),
+    // Evaluate the formula against the subject.
+    Eval(Noun, Noun),
+    // Push the frame and evaluate the formula against the subject.
+    Push(Frame, Noun, Noun),
+}
+
+// rule returns the spec rule step reduces formula by.
+fn rule(spec: Spec, formula: &Noun) -> &'static str {
+    let (head, tail) = match formula.as_cell() {
+        Some(pair) => pair,
+        None => return "*a -> *a",
+    };
+    let op = match head {
+        &Noun::Atom(ref a) => a.as_u64(),
+        &Noun::Cell(_) => return "*[a [b c] d] -> [*[a b c] *[a d]]",
+    };
+    let hinted = tail.as_cell().map_or(false, |(b, _)| b.as_cell().is_some());
+    match (op, spec) {
+        (Some(0), _) => "*[a 0 b] -> /[b a]",
+        (Some(1), _) => "*[a 1 b] -> b",
+        (Some(2), _) => "*[a 2 b c] -> *[*[a b] *[a c]]",
+        (Some(3), _) => "*[a 3 b] -> ?*[a b]",
+        (Some(4), _) => "*[a 4 b] -> +*[a b]",
+        (Some(5), Spec::Nock4K) => "*[a 5 b c] -> =[*[a b] *[a c]]",
+        (Some(5), Spec::Nock5K) => "*[a 5 b] -> =*[a b]",
+        (Some(6), _) => "*[a 6 b c d] -> *[a c] if *[a b] is 0 or *[a d] if it's 1",
+        (Some(7), _) => "*[a 7 b c] -> *[*[a b] c]",
+        (Some(8), _) => "*[a 8 b c] -> *[[*[a b] a] c]",
+        (Some(9), _) => "*[a 9 b c] -> *[*[a c] 2 [0 1] 0 b]",
+        (Some(10), Spec::Nock4K) => "*[a 10 [b c] d] -> #[b *[a c] *[a d]]",
+        (Some(10), Spec::Nock5K) if hinted => "*[a 10 [b c] d] -> *[a 8 c 7 [0 3] d]",
+        (Some(10), Spec::Nock5K) => "*[a 10 b c] -> *[a c]",
+        (Some(11), Spec::Nock4K) if hinted => "*[a 11 [b c] d] -> *[[*[a c] *[a d]] 0 3]",
+        (Some(11), Spec::Nock4K) => "*[a 11 b c] -> *[a c]",
+        (Some(12), Spec::Nock4K) => "*[a 12 b c] -> .^[*[a b] *[a c]]",
+        _ => "*a -> *a",
+    }
+}
+
+/// Takes one step evaluating a nock formula against a subj.
 ///
 /// The head of the formula is expected to be a Noun::Atom or a Noun::Cell that
 /// computes to a Noun::Atom with one of the following values.
@@ -272,163 +1049,900 @@ pub fn compute(noun: Noun) -> Result<Noun, NockError> {
 /// * 0 fas or slot the nock tree addressing algorithm. expects an atom crashes
 ///   if it isn't.
 /// * 1 return the tail unmodified a sort of nock identity function.
-/// * 2 compute the nock evaluation of the tail against the subject.
-/// * 3 wut or nock operation ? which returns the atom 1 if the tail is an atom
-///   or the atom 0 if it's a cell.
-/// * 4 lus increment the atom in the tail. crash if it's not an atom.
-/// * 5 tis return 0 if the head and the tail of a cell are equal. 1 otherwise.
-/// * 6 the nock macro for if then else.
-/// * 7 \*[a 7 b c] -> *[a 2 b 1 c]
-/// * 8 \*[a 8 b c] -> *[a 7 [[7 [0 1] b] 0 1] c]
-/// * 9 \*[a 9 b c] -> *[a 7 c 2 [0 1] 0 b]
+/// * 2 \*[a 2 b c] -> *[*[a b] *[a c]] compute the nock evaluation of a
+///   computed formula against a computed subject.
+/// * 3 wut or nock operation ? which returns the atom 1 if the tail computes
+///   to an atom or the atom 0 if it's a cell.
+/// * 4 lus increment the atom the tail computes to. crash if it's not an atom.
+/// * 5 tis
+///   * 4K \*[a 5 b c] -> =[*[a b] *[a c]]
+///   * 5K \*[a 5 b] -> =*[a b] return 0 if the head and the tail of the cell
+///     the tail computes to are equal. 1 otherwise.
+/// * 6 \*[a 6 b c d] -> *[a c] if *[a b] is 0 or *[a d] if it's 1. Any
+///   other test crashes.
+/// * 7 \*[a 7 b c] -> *[*[a b] c]
+/// * 8 \*[a 8 b c] -> *[[*[a b] a] c]
+/// * 9 \*[a 9 b c] -> *[*[a c] 2 [0 1] 0 b] calls arm b of the core *[a c].
+///   A jetted gate runs its jet instead.
+///
+/// The macros 6 to 9 compute the same products as their rewrites in the
+/// spec without building the rewritten formula.
 /// * 10
-///   * \*[a 10 b c]     -> *[a c]
-///   * \*[a 10 [b c] d] -> *[a 8 c 7 [0 3] d]
+///   * 4K \*[a 10 [b c] d] -> #[b *[a c] *[a d]]
+///   * 5K \*[a 10 b c]     -> *[a c]
+///   * 5K \*[a 10 [b c] d] -> *[a 8 c 7 [0 3] d]
+/// * 11 4K only
+///   * \*[a 11 b c]     -> *[a c]
+///   * \*[a 11 [b c] d] -> *[[*[a c] *[a d]] 0 3]
+///   * %fast hints name the core *[a d] for jets.
+///   * %memo hints cache the product of the hinted formula against a.
+///   * %spot, %mean, %hunk and %lose hints push the clue *[a c] onto the
+///     trace stack printed when the computation crashes.
+/// * 12 4K only \*[a 12 b c] -> reads *[a c] with ref *[a b] from the
+///   interpreter's namespace. The computation crashes if the read blocks.
 /// * Anything else is a nock crash.
-fn nock_internal(subj: &Noun, formula: Noun) -> Result<Noun, NockError> {
-    match formula {
-        Noun::Atom(_) => return Err(NockError::new(format!("!! Nock Infinite Loop"))),
-        cell => {
-            match try!(cell.head()) {
-                &Noun::Atom(a) => {
-                    // We expect an instruction from 0 to 10
-                    match a {
-                        0 => {
-                            let tail = try!(slice_to_noun(try!(cell.tail())));
-                            if let Noun::Atom(b) = tail {
-                                return fas(subj, b);
-                            } else {
-                                return Err(NockError::new(format!("!! not a slot index {}", tail)));
-                            }
-                        }
-                        1 => {
-                            return Ok(try!(slice_to_noun(try!(cell.tail()))));
-                        }
-                        2 => {
-                            return Ok(try!(nock_internal(subj,
-                                                         try!(slice_to_noun(try!(cell.tail()))))));
-                        }
-                        3 => {
-                            return Ok(wut(try!(nock_internal(subj,
-                                                             try!(slice_to_noun(
-                                                    try!(cell.tail())))))));
-                        }
-                        4 => {
-                            let tail_noun = try!(slice_to_noun(try!(cell.tail())));
-                            if let Noun::Cell(_) = tail_noun {
-                                return Ok(try!(lus(try!(nock_internal(subj, tail_noun)))));
-                            }
-                            return Ok(try!(lus(tail_noun)));
-                        }
-                        5 => {
-                            return Ok(try!(tis(try!(slice_to_noun(try!(cell.tail()))))));
-                        }
-                        // macros
-                        6 => {
-                            let tail = try!(cell.tail());
-                            if tail.len() < 3 {
-                                return Err(NockError::new("!! Need 3 Nouns for macro 6"));
-                            }
-                            let b = tail[0].clone();
-                            let c = tail[1].clone();
-                            let d = try!(slice_to_noun(&tail[2..]));
-                            // *[a 6 b c d]     *[a 2 [0 1] 2 [1 c d] [1 0] 2 [1 2 3] [1 0] 4 4 b]
-                            let formula = cell!(atom(2),
-                                                // [0 1]
-                                                cell!(atom(0), atom(1)),
-                                                // 2
-                                                atom(2),
-                                                // [1 c d]
-                                                cell!(atom(1), c, d),
-                                                // [1 0]
-                                                cell!(atom(1), atom(0)),
-                                                // 2
-                                                atom(2),
-                                                // [1 2 3]
-                                                cell!(atom(1), atom(2), atom(3)),
-                                                // [1 0]
-                                                cell!(atom(1), atom(0)),
-                                                // 4 4 b]
-                                                atom(4),
-                                                atom(4),
-                                                b);
-                            return nock_internal(subj, formula);
-                        }
-                        7 => {
-                            let tail = try!(cell.tail());
-                            if tail.len() < 2 {
-                                return Err(NockError::new("!! Need 2 Nouns for macro 7"));
-                            }
-                            let b = tail[0].clone();
-                            let c = tail[1].clone();
-                            // *[a 7 b c] -> *[a 2 b 1 c]
-                            let formula = cell!(atom(2), b, atom(1), c);
-                            return nock_internal(subj, formula);
-                        }
-                        8 => {
-                            let tail = try!(cell.tail());
-                            if tail.len() < 2 {
-                                return Err(NockError::new("!! Need 2 Nouns for macro 8"));
-                            }
-                            let b = tail[0].clone();
-                            let c = tail[1].clone();
-                            // *[a 8 b c]       *[a 7 [[7 [0 1] b] 0 1] c]
-                            let formula = cell!(atom(7),
-                                                cell!(cell!(atom(7), cell!(atom(0), atom(1)), b),
-                                                      atom(0),
-                                                      atom(1)),
-                                                c);
-                            return nock_internal(subj, formula);
-                        }
-                        9 => {
-                            let tail = try!(cell.tail());
-                            if tail.len() < 2 {
-                                return Err(NockError::new("!! Need 2 Nouns for macro 9"));
-                            }
-                            let b = tail[0].clone();
-                            let c = tail[1].clone();
-                            // *[a 9 b c]       *[a 7 c 2 [0 1] 0 b]
-                            let formula =
-                                cell!(atom(7), c, atom(2), cell!(atom(0), atom(1)), atom(0), b);
-                            return nock_internal(subj, formula);
-                        }
-                        10 => {
-                            let tail = try!(cell.tail());
-                            if tail.len() < 2 {
-                                return Err(NockError::new("!! Need at least 2 Nouns for macro 6"));
-                            }
-                            let b = tail[0].clone();
-                            let c = tail[1].clone();
-                            match b {
-                                Noun::Atom(_) => {
-                                    // *[a 10 b c]      *[a c]
-                                    // b is discarded.
-                                    return nock_internal(subj, c);
-                                }
-                                Noun::Cell(list) => {
-                                    let d = c;
-                                    // b is discarded.
-                                    let c = try!(slice_to_noun(&list[1..]));
-                                    // *[a 10 [b c] d]  *[a 8 c 7 [0 3] d]
-                                    let formula =
-                                        cell!(atom(8), c, atom(7), cell!(atom(0), atom(3)), d);
-                                    return nock_internal(subj, formula);
-                                }
-                            }
-                        }
-                        _ => {
-                            return Err(NockError::new(format!("!! Unknown Nock instruction {}",
-                                                              a)));
-                        }
+fn step(ctx: &mut Interpreter, subj: Noun, formula: Noun) -> Result<Step, NockError> {
+    let spec = ctx.spec;
+    let (head, tail) = match formula.as_cell() {
+        Some((head, tail)) => (head.clone(), tail.clone()),
+        None => {
+            return Err(NockError::from(Exit::Formula {
+                opcode: None,
+                formula: formula,
+            }))
+        }
+    };
+    let a = match head {
+        Noun::Atom(a) => a,
+        head_formula => {
+            // *[a [b c] d] -> [*[a b c] *[a d]]
+            return Ok(Step::Push(Frame::ConsHead(subj.clone(), tail), subj, head_formula));
+        }
+    };
+    let unknown = |a: Atom, formula: Noun| {
+        NockError::from(Exit::Opcode {
+            opcode: Noun::Atom(a),
+            formula: formula,
+        })
+    };
+    let op = match a.as_u64() {
+        Some(op) => op,
+        None => return Err(unknown(a, formula)),
+    };
+    // We expect an instruction from 0 to 12
+    match op {
+        0 => {
+            if let Noun::Atom(ref b) = tail {
+                return Ok(Step::Done(try!(fas(&subj, b))));
+            } else {
+                return Err(malformed(op, &formula));
+            }
+        }
+        1 => {
+            return Ok(Step::Done(tail));
+        }
+        2 => {
+            let (b, c) = try!(args(op, &formula, &tail));
+            return Ok(Step::Push(Frame::EvalSubject(subj.clone(), c.clone()), subj, b.clone()));
+        }
+        3 => {
+            return Ok(Step::Push(Frame::Wut, subj, tail));
+        }
+        4 => {
+            return Ok(Step::Push(Frame::Lus, subj, tail));
+        }
+        5 => {
+            if spec == Spec::Nock5K {
+                return Ok(Step::Push(Frame::Tis, subj, tail));
+            }
+            let (b, c) = try!(args(op, &formula, &tail));
+            return Ok(Step::Push(Frame::TisLeft(subj.clone(), c.clone()), subj, b.clone()));
+        }
+        // The macros are computed directly rather than by their rewrites.
+        6 => {
+            let (b, rest) = try!(args(op, &formula, &tail));
+            let (c, d) = match rest.as_cell() {
+                Some(pair) => pair,
+                None => return Err(malformed(op, &formula)),
+            };
+            let frame = Frame::Branch(subj.clone(), c.clone(), d.clone());
+            return Ok(Step::Push(frame, subj, b.clone()));
+        }
+        7 => {
+            let (b, c) = try!(args(op, &formula, &tail));
+            return Ok(Step::Push(Frame::Compose(c.clone()), subj, b.clone()));
+        }
+        8 => {
+            let (b, c) = try!(args(op, &formula, &tail));
+            return Ok(Step::Push(Frame::Pin(subj.clone(), c.clone()), subj, b.clone()));
+        }
+        9 => {
+            let (b, c) = try!(args(op, &formula, &tail));
+            // *[a 9 b c]       *[*[a c] 2 [0 1] 0 b]
+            let b = match b {
+                &Noun::Atom(ref b) => b.clone(),
+                _ => return Err(malformed(op, &formula)),
+            };
+            return Ok(Step::Push(Frame::Call(b), subj, c.clone()));
+        }
+        10 if spec == Spec::Nock4K => {
+            let (bc, d) = try!(args(op, &formula, &tail));
+            let (b, c) = match bc.as_cell() {
+                Some((&Noun::Atom(ref b), c)) => (b, c),
+                _ => return Err(malformed(op, &formula)),
+            };
+            let frame = Frame::EditValue(b.clone(), subj.clone(), d.clone());
+            return Ok(Step::Push(frame, subj, c.clone()));
+        }
+        11 if spec == Spec::Nock4K => {
+            let (b, d) = try!(args(op, &formula, &tail));
+            if let Some((tag, c)) = b.as_cell() {
+                // The dynamic hint is computed but only %fast, %memo and the
+                // trace hints use it.
+                // *[a 11 [b c] d]  *[[*[a c] *[a d]] 0 3]
+                let frame = Frame::Hint(tag.clone(), subj.clone(), d.clone());
+                return Ok(Step::Push(frame, subj, c.clone()));
+            }
+            // *[a 11 b c]      *[a c]
+            if *b == atom(MEMO) {
+                return Ok(memo(ctx, subj, d.clone()));
+            }
+            return Ok(Step::Eval(subj, d.clone()));
+        }
+        12 if spec == Spec::Nock4K => {
+            let (b, c) = try!(args(op, &formula, &tail));
+            return Ok(Step::Push(Frame::ScryRef(subj.clone(), c.clone()), subj, b.clone()));
+        }
+        10 => {
+            let (b, d) = try!(args(op, &formula, &tail));
+            match b.as_cell() {
+                None => {
+                    // *[a 10 b c]      *[a c]
+                    // b is discarded.
+                    return Ok(Step::Eval(subj, d.clone()));
+                }
+                Some((_, c)) => {
+                    // b is discarded.
+                    // *[a 10 [b c] d]  *[a 8 c 7 [0 3] d]
+                    let formula = cell!(atom(8), c.clone(), atom(7), cell!(atom(0), atom(3)), d.clone());
+                    return Ok(Step::Eval(subj, formula));
+                }
+            }
+        }
+        _ => {
+            return Err(unknown(a, formula));
+        }
+    }
+}
+
+// loobean returns true for the loobean 0 (yes) and false for 1 (no) or None
+// if noun isn't a loobean.
+pub(crate) fn loobean(noun: &Noun) -> Option<bool> {
+    match noun {
+        &Noun::Atom(ref a) if a.as_u64() == Some(0) => Some(true),
+        &Noun::Atom(ref a) if a.as_u64() == Some(1) => Some(false),
+        _ => None,
+    }
+}
+
+// arm returns the arm at axis of core for 9 to call.
+pub(crate) fn arm(core: &Noun, axis: &Atom) -> Result<Noun, NockError> {
+    fas(core, axis).map_err(|_| {
+        NockError::from(Exit::Arm {
+            axis: axis.clone(),
+            core: core.clone(),
+        })
+    })
+}
+
+// memo returns the cached product of formula against subj or computes it to
+// be cached.
+fn memo(ctx: &mut Interpreter, subj: Noun, formula: Noun) -> Step {
+    match ctx.memo.get(&subj, &formula) {
+        Some(product) => Step::Done(product),
+        None => Step::Push(Frame::Memo(subj.clone(), formula.clone()), subj, formula),
+    }
+}
+
+// resume hands the product of a sub-formula to the frame waiting on it.
+fn resume(ctx: &mut Interpreter, frame: Frame, product: Noun) -> Result<Step, NockError> {
+    match frame {
+        Frame::ConsHead(subj, tail) => Ok(Step::Push(Frame::ConsTail(product), subj, tail)),
+        Frame::ConsTail(head) => Ok(Step::Done(Noun::cons(head, product))),
+        Frame::EvalSubject(subj, c) => Ok(Step::Push(Frame::EvalFormula(product), subj, c)),
+        Frame::EvalFormula(new_subj) => Ok(Step::Eval(new_subj, product)),
+        Frame::Wut => Ok(Step::Done(wut(product))),
+        Frame::Lus => Ok(Step::Done(try!(lus(product)))),
+        Frame::Tis => Ok(Step::Done(try!(tis(product)))),
+        Frame::TisLeft(subj, c) => Ok(Step::Push(Frame::TisRight(product), subj, c)),
+        Frame::TisRight(left) => Ok(Step::Done(try!(tis(Noun::cons(left, product))))),
+        Frame::EditValue(b, subj, d) => Ok(Step::Push(Frame::EditTarget(b, product), subj, d)),
+        Frame::EditTarget(b, value) => Ok(Step::Done(try!(hax(&b, value, &product)))),
+        Frame::Hint(tag, subj, d) => {
+            if tag == atom(FAST) {
+                return Ok(Step::Push(Frame::Fast(product), subj, d));
+            }
+            if tag == atom(MEMO) {
+                return Ok(memo(ctx, subj, d));
+            }
+            if let Some(hint) = Hint::from_tag(&tag) {
+                return Ok(Step::Push(Frame::Trace(hint, product), subj, d));
+            }
+            Ok(Step::Eval(subj, d))
+        }
+        Frame::Trace(_, _) => Ok(Step::Done(product)),
+        Frame::Memo(subj, formula) => {
+            ctx.memo.insert(subj, formula, product.clone());
+            Ok(Step::Done(product))
+        }
+        Frame::Fast(clue) => {
+            ctx.jets.fast(&clue, &product);
+            Ok(Step::Done(product))
+        }
+        Frame::Branch(subj, c, d) => {
+            match loobean(&product) {
+                Some(true) => Ok(Step::Eval(subj, c)),
+                Some(false) => Ok(Step::Eval(subj, d)),
+                None => Err(NockError::from(Exit::Loobean(product))),
+            }
+        }
+        Frame::Compose(c) => Ok(Step::Eval(product, c)),
+        Frame::Pin(subj, c) => Ok(Step::Eval(Noun::cons(product, subj), c)),
+        Frame::Call(axis) => {
+            let arm = try!(arm(&product, &axis));
+            if let Some((name, jet)) = ctx.jets.find(&product, &axis) {
+                let sample = try!(fas(&product, &Atom::Direct(6)));
+                let start = Instant::now();
+                let jetted = jet(&sample);
+                if let Some(ref mut profile) = ctx.profile {
+                    profile.jet(&name, start.elapsed());
+                }
+                if let Some(jetted) = jetted {
+                    if ctx.jets.is_verifying() {
+                        return Ok(Step::Push(Frame::Verify(name, sample, jetted), product, arm));
                     }
+                    return Ok(Step::Done(jetted));
                 }
-                head_formula => {
-                    let head = try!(nock_internal(subj, head_formula.clone()));
-                    let new_formula = try!(slice_to_noun(try!(cell.tail())));
-                    let tail_noun = try!(nock_internal(subj, new_formula));
-                    return Ok(cell!(head, tail_noun));
+            }
+            Ok(Step::Eval(product, arm))
+        }
+        Frame::Verify(name, sample, jetted) => {
+            if jetted != product {
+                ctx.jets.mismatch(Mismatch {
+                    name: name,
+                    sample: sample,
+                    jet: jetted,
+                    nock: product.clone(),
+                });
+            }
+            Ok(Step::Done(product))
+        }
+        Frame::ScryRef(subj, c) => Ok(Step::Push(Frame::ScryPath(product), subj, c)),
+        Frame::ScryPath(reference) => {
+            match ctx.namespace.scry(&reference, &product) {
+                Some(noun) => Ok(Step::Done(noun)),
+                None => {
+                    Err(NockError::from(Bail::Blocked {
+                        reference: reference,
+                        path: product,
+                    }))
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+fn parse_noun(expr: &str) -> Noun {
+    use parser::Parser;
+    use tokenizer::tokenizer_tests::MockReader;
+    let reader = MockReader::new(vec![expr.to_string()]);
+    Parser::new(Box::new(reader)).parse().unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_spec_rules() {
+    let cases = vec![
+        // slot
+        ("[[[4 5] [6 14 15]] [0 7]]", "[14 15]"),
+        // constant
+        ("[42 [1 153 218]]", "[153 218]"),
+        // evaluate
+        ("[77 [2 [1 42] [1 1 153 218]]]", "[153 218]"),
+        // cell test
+        ("[57 [3 0 1]]", "1"),
+        ("[[132 19] [3 0 1]]", "0"),
+        // increment
+        ("[57 [4 0 1]]", "58"),
+        // equality
+        ("[[132 19] [5 [0 2] [0 3]]]", "1"),
+        ("[[19 19] [5 [0 2] [0 3]]]", "0"),
+        ("[42 [5 [1 1 2] [1 1 2]]]", "0"),
+        // autocons
+        ("[42 [[4 0 1] [3 0 1]]]", "[43 1]"),
+        // if then else
+        ("[42 [6 [1 0] [4 0 1] [1 233]]]", "43"),
+        ("[42 [6 [1 1] [4 0 1] [1 233]]]", "233"),
+        // compose
+        ("[42 [7 [4 0 1] [4 0 1]]]", "44"),
+        // push
+        ("[42 [8 [4 0 1] [0 1]]]", "[43 42]"),
+        // call an arm of a core
+        ("[[[4 0 3] 41] [9 2 0 1]]", "42"),
+        // edit
+        ("[[1 2 3] [10 [2 1 7] 0 1]]", "[7 2 3]"),
+        ("[[1 2 3] [10 [7 4 0 7] 0 1]]", "[1 2 4]"),
+        ("[[1 2 3] [10 [1 1 7] 0 1]]", "7"),
+        // hints
+        ("[42 [11 1 [4 0 1]]]", "43"),
+        ("[42 [11 [1 [1 0]] [4 0 1]]]", "43"),
+    ];
+    for (expr, expected) in cases {
+        let result = compute(parse_noun(expr)).expect(expr);
+        assert_eq!(format!("{}", result), expected);
+    }
+}
+
+// Rng is a xorshift generator so the property tests are repeatable.
+#[cfg(test)]
+struct Rng(u64);
+
+#[cfg(test)]
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    fn noun(&mut self, depth: u32) -> Noun {
+        if depth == 0 || self.below(3) == 0 {
+            return atom(self.below(4));
+        }
+        Noun::cons(self.noun(depth - 1), self.noun(depth - 1))
+    }
+
+    // formula returns a random formula that tends to compute something
+    // rather than crash.
+    fn formula(&mut self, depth: u32) -> Noun {
+        if depth == 0 {
+            return match self.below(2) {
+                0 => cell!(atom(0), atom(1 + self.below(7))),
+                _ => Noun::cons(atom(1), self.noun(2)),
+            };
+        }
+        let depth = depth - 1;
+        match self.below(10) {
+            0 => cell!(atom(0), atom(1 + self.below(7))),
+            1 => Noun::cons(atom(1), self.noun(3)),
+            2 => Noun::cons(atom(3), self.formula(depth)),
+            3 => Noun::cons(atom(4), self.formula(depth)),
+            4 => cell!(atom(5), self.formula(depth), self.formula(depth)),
+            5 => {
+                // A test that's usually a loobean.
+                let test = match self.below(3) {
+                    0 => Noun::cons(atom(3), self.formula(depth)),
+                    1 => cell!(atom(5), self.formula(depth), self.formula(depth)),
+                    _ => self.formula(depth),
+                };
+                cell!(atom(6), test, self.formula(depth), self.formula(depth))
+            }
+            6 => cell!(atom(7), self.formula(depth), self.formula(depth)),
+            7 => cell!(atom(8), self.formula(depth), self.formula(depth)),
+            8 => cell!(atom(9), atom(1 + self.below(7)), self.formula(depth)),
+            _ => Noun::cons(self.formula(depth), self.formula(depth)),
+        }
+    }
+}
+
+// rewrite replaces the macros 6 to 9 in formula with their rewrites from the
+// spec so only 0 to 5 are left.
+#[cfg(test)]
+fn rewrite(formula: &Noun) -> Noun {
+    let (head, tail) = match formula.as_cell() {
+        Some(pair) => pair,
+        None => return formula.clone(),
+    };
+    let op = match head {
+        &Noun::Cell(_) => return Noun::cons(rewrite(head), rewrite(tail)),
+        &Noun::Atom(ref a) => a.as_u64().unwrap(),
+    };
+    if op < 2 {
+        return formula.clone();
+    }
+    if op == 3 || op == 4 {
+        return Noun::cons(head.clone(), rewrite(tail));
+    }
+    let (b, c) = tail.as_cell().unwrap();
+    match op {
+        6 => {
+            let (c, d) = c.as_cell().unwrap();
+            // *[a 6 b c d] -> *[a 2 [0 1] 2 [1 c d] [1 0] 2 [1 2 3] [1 0] 4 4 b]
+            cell!(atom(2),
+                  cell!(atom(0), atom(1)),
+                  atom(2),
+                  cell!(atom(1), rewrite(c), rewrite(d)),
+                  cell!(atom(1), atom(0)),
+                  atom(2),
+                  cell!(atom(1), atom(2), atom(3)),
+                  cell!(atom(1), atom(0)),
+                  atom(4),
+                  atom(4),
+                  rewrite(b))
+        }
+        // *[a 7 b c] -> *[a 2 b 1 c]
+        7 => cell!(atom(2), rewrite(b), atom(1), rewrite(c)),
+        // *[a 8 b c] -> *[a 7 [[7 [0 1] b] 0 1] c]
+        8 => {
+            let pin = cell!(cell!(atom(7), cell!(atom(0), atom(1)), b.clone()), atom(0), atom(1));
+            rewrite(&cell!(atom(7), pin, c.clone()))
+        }
+        // *[a 9 b c] -> *[*[a c] 2 [0 1] 0 b] which is *[a 7 c 2 [0 1] 0 b]
+        9 => {
+            let call = cell!(atom(2), cell!(atom(0), atom(1)), atom(0), b.clone());
+            rewrite(&cell!(atom(7), c.clone(), call))
+        }
+        _ => cell!(head.clone(), rewrite(b), rewrite(c)),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_macros_match_rewrites() {
+    // The arms 9 calls are random nouns so some computations loop forever.
+    let limits = Limits { max_steps: Some(10000) };
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    let mut products = 0;
+    for _ in 0..2000 {
+        let subject = rng.noun(4);
+        let formula = rng.formula(4);
+        let native = compute_with_limits(Noun::cons(subject.clone(), formula.clone()), limits);
+        let rewritten = compute_with_limits(Noun::cons(subject.clone(), rewrite(&formula)), limits);
+        match (native, rewritten) {
+            (Ok(native), Ok(rewritten)) => {
+                assert_eq!(native.product, rewritten.product, "*[{} {}]", subject, formula);
+                products += 1;
+            }
+            (Err(ref err), _) | (_, Err(ref err)) if err.is_out_of_fuel() => (),
+            (Err(_), Err(_)) => (),
+            (native, rewritten) => {
+                panic!("*[{} {}] computed {:?} natively but {:?} rewritten",
+                       subject,
+                       formula,
+                       native.map(|c| c.product),
+                       rewritten.map(|c| c.product))
+            }
+        }
+    }
+    // Enough formulas compute something for the test to mean something.
+    assert!(products > 500, "only {} formulas computed", products);
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_legacy_rules() {
+    let cases = vec![
+        // equality
+        ("[[132 19] [5 0 1]]", "1"),
+        ("[[19 19] [5 0 1]]", "0"),
+        // hints
+        ("[42 [10 1 [4 0 1]]]", "43"),
+        ("[42 [10 [1 [1 0]] [4 0 1]]]", "43"),
+    ];
+    for (expr, expected) in cases {
+        let result = compute_with_spec(parse_noun(expr), Spec::Nock5K).expect(expr);
+        assert_eq!(format!("{}", result), expected);
+    }
+    assert!(compute_with_spec(parse_noun("[42 [11 1 [4 0 1]]]"), Spec::Nock5K).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_scry() {
+    // Binds [ref path] at the path 1 and blocks on everything else.
+    struct Echo;
+    impl Namespace for Echo {
+        fn scry(&self, reference: &Noun, path: &Noun) -> Option<Noun> {
+            if *path == atom(1) {
+                Some(Noun::cons(reference.clone(), path.clone()))
+            } else {
+                None
+            }
+        }
+    }
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    assert!(interpreter.compute(parse_noun("[42 [12 [1 0] [1 1]]]")).is_err());
+    interpreter.set_namespace(Box::new(Echo));
+    let result = interpreter.compute(parse_noun("[42 [12 [0 1] [1 1]]]")).unwrap();
+    assert_eq!(format!("{}", result), "[42 1]");
+    assert!(interpreter.compute(parse_noun("[42 [12 [0 1] [1 2]]]")).is_err());
+    assert!(interpreter.compute(parse_noun("[42 [12 [0 1] [0 2]]]")).is_err());
+    assert!(Interpreter::new(Spec::Nock5K).compute(parse_noun("[42 [12 [1 0] [1 1]]]")).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_deep() {
+    // [[[[0 1] 0 1] 0 1] 0 1] nests autocons a million deep which would
+    // overflow the native stack if the evaluator recursed.
+    let depth = 1000000;
+    let mut formula = cell!(atom(0), atom(1));
+    let mut expected = atom(42);
+    for _ in 0..depth {
+        formula = Noun::cons(formula, cell!(atom(0), atom(1)));
+        expected = Noun::cons(expected, atom(42));
+    }
+    let noun = Noun::cons(atom(42), formula.clone());
+    assert_eq!(compute(noun.clone()).unwrap(), expected);
+
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    interpreter.set_stack_limit(depth / 2);
+    assert!(interpreter.compute(noun).is_err());
+
+    // A decrement loop recurses through 9 once per step.
+    let dec = parse_noun("[100000 [8 [1 0] 8 [1 6 [5 [0 7] 4 0 6] [0 6] 9 2 [0 2] [4 0 6] 0 7] 9 2 0 1]]");
+    assert_eq!(compute(dec).unwrap(), atom(99999));
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_with_limits() {
+    // [4 0 1] is one step for the increment and one for the slot.
+    let unlimited = compute_with_limits(parse_noun("[42 [4 0 1]]"), Limits::default()).unwrap();
+    assert_eq!(unlimited.product, atom(43));
+    assert_eq!(unlimited.steps, 2);
+    let limits = Limits { max_steps: Some(2) };
+    assert_eq!(compute_with_limits(parse_noun("[42 [4 0 1]]"), limits).unwrap().steps, 2);
+    let limits = Limits { max_steps: Some(1) };
+    let err = compute_with_limits(parse_noun("[42 [4 0 1]]"), limits).unwrap_err();
+    assert!(err.is_out_of_fuel());
+
+    // *[a 2 [0 1] 0 1] with a = [2 [0 1] 0 1] runs forever.
+    let limits = Limits { max_steps: Some(10000) };
+    let err = compute_with_limits(parse_noun("[[2 [0 1] 0 1] [2 [0 1] 0 1]]"), limits)
+        .unwrap_err();
+    assert!(err.is_out_of_fuel());
+    assert!(!compute_with_limits(parse_noun("[42 [0 2]]"), limits).unwrap_err().is_out_of_fuel());
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_interrupted() {
+    use std::thread;
+    use std::time::Duration;
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    interpreter.set_interrupt(flag.clone());
+    assert_eq!(interpreter.compute(parse_noun("[42 [4 0 1]]")).unwrap(), atom(43));
+
+    let setter = flag.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        setter.store(true, Ordering::SeqCst);
+    });
+    let err = interpreter.compute(parse_noun("[[2 [0 1] 0 1] [2 [0 1] 0 1]]")).unwrap_err();
+    handle.join().unwrap();
+    assert!(err.is_interrupted());
+    assert!(!err.is_out_of_fuel());
+    assert!(!flag.load(Ordering::SeqCst));
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_traced() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    // Records each step's depth, formula and rule and each product.
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+    impl Tracer for Recorder {
+        fn step(&mut self, depth: usize, _: &Noun, formula: &Noun, rule: &str) {
+            self.0.borrow_mut().push(format!("{} {} {}", depth, formula, rule));
+        }
+        fn product(&mut self, depth: usize, product: &Noun) {
+            self.0.borrow_mut().push(format!("{} -> {}", depth, product));
+        }
+    }
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    interpreter.set_tracer(Some(Box::new(Recorder(events.clone()))));
+    interpreter.compute(parse_noun("[42 [7 [4 0 1] [0 1]]]")).unwrap();
+    assert_eq!(*events.borrow(),
+               vec!["0 [7 [4 0 1] 0 1] *[a 7 b c] -> *[*[a b] c]",
+                    "1 [4 0 1] *[a 4 b] -> +*[a b]",
+                    "2 [0 1] *[a 0 b] -> /[b a]",
+                    "2 -> 42",
+                    "1 -> 43",
+                    "0 [0 1] *[a 0 b] -> /[b a]",
+                    "0 -> 43"]);
+}
+
+// dec computes a dec gate [battery sample 0] that counts up to its sample.
+// The formula names it %dec with a %fast hint and calls it.
+#[cfg(test)]
+fn dec(sample: Noun) -> Noun {
+    let battery = parse_noun("[8 [1 0] 8 [1 6 [5 [0 30] 4 0 6] [0 6] 9 2 10 [6 4 0 6] 0 1] 9 2 0 1]");
+    let formula = parse_noun("[7 [11 [1953718630 1 6514020 0] 0 1] 9 2 0 1]");
+    cell!(cell!(battery, sample, atom(0)), formula)
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_jetted() {
+    use std::str::FromStr;
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    let slow = interpreter.compute_with_limits(dec(atom(1000)), Limits::default()).unwrap();
+    assert_eq!(slow.product, atom(999));
+    assert!(slow.steps > 1000);
+
+    interpreter.set_jets(Jets::with_defaults());
+    let fast = interpreter.compute_with_limits(dec(atom(1000)), Limits::default()).unwrap();
+    assert_eq!(fast.product, atom(999));
+    assert!(fast.steps < 10);
+    let big = Noun::Atom(Atom::from_str("18446744073709551616").unwrap());
+    assert_eq!(interpreter.compute(dec(big)).unwrap(), atom(::std::u64::MAX));
+    // The jet punts on 0 so the gate's nock runs and never finishes.
+    let limits = Limits { max_steps: Some(10000) };
+    assert!(interpreter.compute_with_limits(dec(atom(0)), limits).unwrap_err().is_out_of_fuel());
+
+    // A wrong jet is caught when verifying but the nock product is used.
+    let mut jets = Jets::new();
+    jets.register("dec", |_| Some(atom(7)));
+    jets.set_verify(true);
+    interpreter.set_jets(jets);
+    assert_eq!(interpreter.compute(dec(atom(10))).unwrap(), atom(9));
+    let mismatches = interpreter.jets().take_mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].name, "dec");
+    assert_eq!(mismatches[0].sample, atom(10));
+    assert_eq!(mismatches[0].jet, atom(7));
+    assert_eq!(mismatches[0].nock, atom(9));
+    assert!(interpreter.jets().take_mismatches().is_empty());
+}
+
+// cut computes a cut gate [battery sample helpers] named %cut with a %fast
+// hint. The helpers are a battery of arms over bits that count one at a
+// time but stop once the atom they shift runs out so a large bloq stays
+// cheap on a small atom.
+#[cfg(test)]
+fn cut(sample: Noun) -> Noun {
+    // half [n [k t]] is n / 2.
+    let half = parse_noun("[6 [5 [0 15] 0 6] [0 14] 6 [5 [4 0 15] 0 6] [0 14] 7 [[0 2] [0 6] [4 0 14] \
+                           4 4 0 15] 9 16 0 1]");
+    // dbl [y [i t]] is 2 * y.
+    let dbl = parse_noun("[6 [5 [0 14] 0 6] [0 15] 7 [[0 2] [0 6] [4 0 14] 4 4 0 15] 9 17 0 1]");
+    // sub [x [z r]] is x - z.
+    let sub = parse_noun("[6 [5 [0 14] 0 6] [0 15] 7 [[0 2] [0 6] [4 0 14] 4 0 15] 9 18 0 1]");
+    // rsh [i [a d]] and lsh [i [a d]] shift d by 2^(a - i) bits.
+    let shift = |arm: u64, step: u64| {
+        parse_noun(&format!("[6 [5 [1 0] 0 15] [1 0] 6 [5 [0 6] 0 14] [7 [[0 2] [0 15] [1 0] 1 0] 9 {1} \
+                             0 1] 7 [[0 2] [4 0 6] [0 14] 7 [[0 2] [4 0 6] [0 14] 0 15] 9 {0} 0 1] \
+                             9 {0} 0 1]",
+                            arm,
+                            step))
+    };
+    // rshn [j [b [a x]]] and lshn [j [b [a x]]] shift x by b 2^a bit blocks.
+    let blocks = |arm: u64, shift: u64| {
+        parse_noun(&format!("[6 [5 [0 6] 0 14] [0 31] 6 [5 [1 0] 0 31] [1 0] 7 [[0 2] [4 0 6] [0 14] \
+                             [0 30] 7 [[0 2] [1 0] [0 30] 0 31] 9 {1} 0 1] 9 {0} 0 1]",
+                            arm,
+                            shift))
+    };
+    // cut [a [[b c] d]] is d shifted right by b blocks less the part of it
+    // past c blocks.
+    let arm = parse_noun("[8 [7 [[0 2] [1 0] [0 28] [0 6] 0 15] 9 21 0 1] 8 [7 [[0 6] [1 0] [0 61] \
+                          [0 14] 0 2] 9 21 0 1] 7 [[0 14] [0 6] [7 [[0 14] [1 0] [0 125] [0 30] \
+                          0 2] 9 22 0 1] 1 0] 9 18 0 1]");
+    let helpers = cell!(cell!(cell!(half, dbl), cell!(sub, shift(19, 16))),
+                        cell!(cell!(shift(20, 17), blocks(21, 19)), cell!(blocks(22, 20), arm)));
+    let battery = parse_noun("[7 [[0 7] 0 6] 9 23 0 1]");
+    let formula = parse_noun("[7 [11 [1953718630 1 7632227 0] 0 1] 9 2 0 1]");
+    cell!(cell!(battery, sample, helpers), formula)
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_jetted_cut() {
+    let cases = vec![// (cut 1 [1 2] 0b1011.0110) is 0b1101.
+                     ((1, 1, 2, 0b1011_0110), 0b1101),
+                     ((0, 2, 3, 0b111_0110), 0b101),
+                     ((2, 1, 0, 0xff), 0),
+                     // A bloq too large to count up to.
+                     ((32, 0, 1, 5), 5),
+                     ((63, 0, 2, 7), 7),
+                     ((40, 1, 1, 5), 0),
+                     ((64, 0, 1, 5), 5),
+                     ((64, 1, 1, 5), 0)];
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    for &((a, b, c, d), expected) in cases.iter() {
+        let sample = cell!(atom(a), cell!(atom(b), atom(c)), atom(d));
+        assert_eq!(interpreter.compute(cut(sample)).unwrap(), atom(expected));
+    }
+    // The jet agrees with the gate's nock.
+    let mut jets = Jets::with_defaults();
+    jets.set_verify(true);
+    interpreter.set_jets(jets);
+    for &((a, b, c, d), expected) in cases.iter() {
+        let sample = cell!(atom(a), cell!(atom(b), atom(c)), atom(d));
+        assert_eq!(interpreter.compute(cut(sample)).unwrap(), atom(expected));
+    }
+    assert!(interpreter.jets().take_mismatches().is_empty());
+}
+
+#[cfg(test)]
+#[test]
+fn test_code_cache_forgets_oldest() {
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    for i in 0..MAX_CODES as u64 + 1 {
+        interpreter.code(&cell!(atom(1), atom(i)));
+    }
+    assert_eq!(interpreter.codes.len(), MAX_CODES);
+    assert!(!interpreter.codes.contains_key(&cell!(atom(1), atom(0))));
+    assert!(interpreter.codes.contains_key(&cell!(atom(1), atom(1))));
+    assert!(interpreter.codes.contains_key(&cell!(atom(1), atom(MAX_CODES as u64))));
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_memoized() {
+    // A gate that builds a tree of depth 7 - k from two recursive calls each
+    // cached by a %memo hint.
+    fn tree(k: u64) -> Noun {
+        let arm = parse_noun("[11 1869440365 6 [5 [0 6] 0 7] [1 0] [9 2 10 [6 4 0 6] 0 1] 9 2 10 [6 4 0 6] 0 1]");
+        let core = cell!(arm, atom(k), atom(7));
+        cell!(core, parse_noun("[9 2 0 1]"))
+    }
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    interpreter.memo().set_capacity(0);
+    let slow = interpreter.compute_with_limits(tree(0), Limits::default()).unwrap();
+    assert_eq!(interpreter.memo().stats().hits, 0);
+
+    interpreter.memo().set_capacity(100);
+    interpreter.memo().clear();
+    let fast = interpreter.compute_with_limits(tree(0), Limits::default()).unwrap();
+    assert_eq!(fast.product, slow.product);
+    assert!(fast.steps * 5 < slow.steps);
+    let stats = interpreter.memo().stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (8, 7, 8));
+    // The cache outlives the computation so the call, its core and the
+    // hint are the only steps.
+    let again = interpreter.compute_with_limits(tree(3), Limits::default()).unwrap();
+    assert_eq!(again.steps, 3);
+    assert_eq!(interpreter.memo().stats().hits, 8);
+
+    // The dynamic hint caches too.
+    interpreter.memo().clear();
+    let formula = "[42 [11 [1869440365 1 0] 4 0 1]]";
+    assert_eq!(interpreter.compute(parse_noun(formula)).unwrap(), atom(43));
+    assert_eq!(interpreter.compute(parse_noun(formula)).unwrap(), atom(43));
+    assert_eq!(interpreter.memo().stats().hits, 1);
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_profiled() {
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    assert!(interpreter.profile().is_none());
+    interpreter.set_profile(Some(Profile::new()));
+    interpreter.compute(parse_noun("[42 [7 [4 0 1] [0 1]]]")).unwrap();
+    {
+        let profile = interpreter.profile().unwrap();
+        assert_eq!(profile.steps(), 4);
+        assert_eq!((profile.opcode(7), profile.opcode(2), profile.opcode(4)), (1, 0, 1));
+        assert_eq!((profile.opcode(0), profile.opcode(1)), (2, 0));
+        assert_eq!(profile.max_depth(), 2);
+    }
+    // Profiles accumulate across computations.
+    interpreter.set_jets(Jets::with_defaults());
+    interpreter.compute(dec(atom(1000))).unwrap();
+    interpreter.compute(dec(atom(1000))).unwrap();
+    let profile = interpreter.profile().unwrap();
+    assert_eq!(profile.jet_calls("dec"), 2);
+    assert_eq!(profile.opcode(9), 2);
+    // [0 1] is reduced twice by each computation.
+    let (_, formula, count) = profile.top(1)[0];
+    assert_eq!((formula, count), (&parse_noun("[0 1]"), 6));
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_crash_trace() {
+    // *[42 11 [%spot 1 [/foo/hoon [1 2] 3 4]] 11 [%mean 1 trap] 11 [%mean 1 'oops'] 0 2]
+    // where the trap *[[[1 %leaf "hi"] 0] 1 %leaf "hi"] is [%leaf "hi"].
+    let spot = "[1953460339 1 [7303014 1852796776 0] [1 2] 3 4]";
+    let trap = "[1851876717 1 [[1 1717658988 104 105 0] 0]]";
+    let expr = format!("[42 [11 {} [11 {} [11 [1851876717 1 1936748399] [0 2]]]]]", spot, trap);
+    let err = compute(parse_noun(&expr)).unwrap_err();
+    assert_eq!(err.to_string(),
+               "NockError: !! Can't take axis 2 of 42\n/foo/hoon:<[1 2].[3 4]>\nhi\noops\n");
+    // Hints that finished aren't in the trace.
+    let expr = format!("[42 [8 [11 {} [0 1]] [0 6]]]", spot);
+    assert_eq!(compute(parse_noun(&expr)).unwrap_err().to_string(),
+               compute(parse_noun("[[42 42] [0 6]]")).unwrap_err().to_string());
+    // Running out of fuel keeps its trace.
+    let limits = Limits { max_steps: Some(3) };
+    let expr = format!("[42 [11 {} [4 4 0 1]]]", spot);
+    let err = compute_with_limits(parse_noun(&expr), limits).unwrap_err();
+    assert!(err.is_out_of_fuel());
+    assert!(err.to_string().ends_with("\n/foo/hoon:<[1 2].[3 4]>\n"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_bails() {
+    let bail = |expr: &str| compute(parse_noun(expr)).unwrap_err().bail().clone();
+    assert_eq!(bail("[42 [0 2]]"),
+               Bail::Exit(Exit::Slot {
+                   axis: Atom::Direct(2),
+                   noun: atom(42),
+               }));
+    assert_eq!(bail("[42 [4 1 [1 2]]]"), Bail::Exit(Exit::Increment(parse_noun("[1 2]"))));
+    assert_eq!(bail("[[1 2] [10 [6 1 7] 0 1]]"),
+               Bail::Exit(Exit::Edit {
+                   axis: Atom::Direct(6),
+                   noun: parse_noun("[1 2]"),
+               }));
+    assert_eq!(bail("[42 [99 0 1]]"),
+               Bail::Exit(Exit::Opcode {
+                   opcode: atom(99),
+                   formula: parse_noun("[99 0 1]"),
+               }));
+    assert_eq!(bail("[42 [9 [0 1] 0 1]]"),
+               Bail::Exit(Exit::Formula {
+                   opcode: Some(9),
+                   formula: parse_noun("[9 [0 1] 0 1]"),
+               }));
+    assert_eq!(bail("42"),
+               Bail::Exit(Exit::Formula {
+                   opcode: None,
+                   formula: atom(42),
+               }));
+    assert_eq!(bail("[42 [6 [1 2] [1 0] 1 1]]"), Bail::Exit(Exit::Loobean(atom(2))));
+    assert_eq!(bail("[42 [6 [1 0] 1]]"),
+               Bail::Exit(Exit::Formula {
+                   opcode: Some(6),
+                   formula: parse_noun("[6 [1 0] 1]"),
+               }));
+    assert_eq!(bail("[42 [9 2 0 1]]"),
+               Bail::Exit(Exit::Arm {
+                   axis: Atom::Direct(2),
+                   core: atom(42),
+               }));
+    assert_eq!(compute_with_spec(parse_noun("[42 [5 1 7]]"), Spec::Nock5K).unwrap_err().bail(),
+               &Bail::Exit(Exit::Compare(atom(7))));
+    assert_eq!(bail("[42 [12 [1 0] [1 1 2 0]]]"),
+               Bail::Blocked {
+                   reference: atom(0),
+                   path: parse_noun("[1 2 0]"),
+               });
+    let limits = Limits { max_steps: Some(1) };
+    let err = compute_with_limits(parse_noun("[42 [4 0 1]]"), limits).unwrap_err();
+    assert_eq!(err.bail(), &Bail::Fuel { steps: 1 });
+    assert_eq!(err.exit_code(), 5);
+    let mut interpreter = Interpreter::new(Spec::Nock4K);
+    interpreter.set_stack_limit(1);
+    let err = interpreter.compute(parse_noun("[42 [4 4 0 1]]")).unwrap_err();
+    assert_eq!(err.bail(), &Bail::Oom { frames: 1 });
+}
+
+#[cfg(test)]
+#[test]
+fn test_compute_crashes() {
+    let cases = vec![
+        "42",
+        "[42 [0 2]]",
+        "[42 [4 1 [1 2]]]",
+        "[42 [5 1 7]]",
+        "[[19 19] [5 0 1]]",
+        "[42 [10 1 [4 0 1]]]",
+        "[42 [10 [0 1] 0 1]]",
+        "[[1 2] [10 [6 1 7] 0 1]]",
+        "[42 [11 [1 [0 2]] [4 0 1]]]",
+        "[42 [6 [1 0] [4 0 1]]]",
+        "[42 [99 0 1]]",
+    ];
+    for expr in cases {
+        assert!(compute(parse_noun(expr)).is_err(), expr);
+    }
+}
@@ -0,0 +1,186 @@
+//! order implements Hoon's canonical noun orderings.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cmp::Ordering;
+
+use atom::Atom;
+use parser::Noun;
+
+impl Noun {
+    /// dor is tree order. It returns true if self sorts before or is equal
+    /// to other.
+    ///
+    /// Atoms sort before cells and by value amongst themselves. Cells sort
+    /// by their heads and then by their tails.
+    pub fn dor(&self, other: &Noun) -> bool {
+        let mut a = self;
+        let mut b = other;
+        loop {
+            if a == b {
+                return true;
+            }
+            match (a, b) {
+                (&Noun::Atom(ref a), &Noun::Atom(ref b)) => return a < b,
+                (&Noun::Atom(_), &Noun::Cell(_)) => return true,
+                (&Noun::Cell(_), &Noun::Atom(_)) => return false,
+                (&Noun::Cell(ref x), &Noun::Cell(ref y)) => {
+                    if x.head() == y.head() {
+                        a = x.tail();
+                        b = y.tail();
+                    } else {
+                        a = x.head();
+                        b = y.head();
+                    }
+                }
+            }
+        }
+    }
+
+    /// gor is mug order. It returns true if self sorts before or is equal
+    /// to other.
+    ///
+    /// Nouns sort by mug falling back to dor when the mugs collide.
+    pub fn gor(&self, other: &Noun) -> bool {
+        let c = self.mug();
+        let d = other.mug();
+        if c == d {
+            return self.dor(other);
+        }
+        c < d
+    }
+
+    /// mor is double mug order. It returns true if self sorts before or is
+    /// equal to other.
+    ///
+    /// Nouns sort by the mug of their mug falling back to dor when those
+    /// collide.
+    pub fn mor(&self, other: &Noun) -> bool {
+        let c = double_mug(self);
+        let d = double_mug(other);
+        if c == d {
+            return self.dor(other);
+        }
+        c < d
+    }
+}
+
+fn double_mug(noun: &Noun) -> u32 {
+    Noun::Atom(Atom::Direct(noun.mug() as u64)).mug()
+}
+
+// ordering turns one of the boolean orders into an Ordering.
+fn ordering<F: Fn(&Noun, &Noun) -> bool>(a: &Noun, b: &Noun, order: F) -> Ordering {
+    if a == b {
+        Ordering::Equal
+    } else if order(a, b) {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+macro_rules! order_wrapper {
+    ($name:ident, $method:ident) => {
+        impl PartialEq for $name {
+            fn eq(&self, other: &$name) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &$name) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &$name) -> Ordering {
+                ordering(&self.0, &other.0, Noun::$method)
+            }
+        }
+    };
+}
+
+/// Dor wraps a Noun so Rust collections order it by dor.
+#[derive(Debug,Clone)]
+pub struct Dor(pub Noun);
+order_wrapper!(Dor, dor);
+
+/// Gor wraps a Noun so Rust collections order it by gor.
+#[derive(Debug,Clone)]
+pub struct Gor(pub Noun);
+order_wrapper!(Gor, gor);
+
+/// Mor wraps a Noun so Rust collections order it by mor.
+#[derive(Debug,Clone)]
+pub struct Mor(pub Noun);
+order_wrapper!(Mor, mor);
+
+#[cfg(test)]
+mod order_tests {
+    use std::collections::BTreeSet;
+    use order::{Dor, Gor, Mor};
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_dor() {
+        assert!(atom(1).dor(&atom(2)));
+        assert!(!atom(2).dor(&atom(1)));
+        assert!(atom(2).dor(&atom(2)));
+        assert!(atom(99).dor(&cell!(atom(1), atom(2))));
+        assert!(!cell!(atom(1), atom(2)).dor(&atom(99)));
+        assert!(cell!(atom(1), atom(2)).dor(&cell!(atom(1), atom(3))));
+        assert!(cell!(atom(1), atom(9)).dor(&cell!(atom(2), atom(0))));
+        assert!(!cell!(cell!(atom(1), atom(2)), atom(0)).dor(&cell!(atom(5), atom(0))));
+    }
+
+    #[test]
+    fn test_gor_and_mor_are_mug_orders() {
+        let a = atom(1);
+        let b = atom(2);
+        assert_eq!(a.gor(&b), a.mug() < b.mug());
+        assert_eq!(b.gor(&a), b.mug() < a.mug());
+        assert!(a.gor(&a));
+        assert!(a.mor(&a));
+        assert!(a.mor(&b) != b.mor(&a));
+    }
+
+    #[test]
+    fn test_wrappers_sort_collections() {
+        let nouns = vec![cell!(atom(1), atom(2)), atom(3), atom(1), cell!(atom(0), atom(9)), atom(3)];
+        let sorted: Vec<Noun> = nouns.iter()
+            .cloned()
+            .map(Dor)
+            .collect::<BTreeSet<Dor>>()
+            .into_iter()
+            .map(|d| d.0)
+            .collect();
+        assert_eq!(sorted,
+                   vec![atom(1), atom(3), cell!(atom(0), atom(9)), cell!(atom(1), atom(2))]);
+
+        let by_gor: Vec<Noun> = nouns.iter().cloned().map(Gor).collect::<BTreeSet<Gor>>()
+            .into_iter().map(|g| g.0).collect();
+        assert_eq!(by_gor.len(), 4);
+        for pair in by_gor.windows(2) {
+            assert!(pair[0].gor(&pair[1]));
+        }
+        let by_mor: Vec<Noun> = nouns.iter().cloned().map(Mor).collect::<BTreeSet<Mor>>()
+            .into_iter().map(|m| m.0).collect();
+        for pair in by_mor.windows(2) {
+            assert!(pair[0].mor(&pair[1]));
+        }
+    }
+}
@@ -14,97 +14,221 @@
 // limitations under the License.
 #![macro_use]
 
+use std::cell;
 use std::error;
 use std::str::FromStr;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::mem;
+use std::rc::Rc;
 
+use arena::InternId;
+use atom::Atom;
 use tokenizer::{Tokenizer, Token, TokenizerError, ExpressionReader};
 
 /// A Noun is an Atom or a Cell.
-#[derive(Debug,PartialEq,Clone)]
+///
+/// A Cell is always a pair of a head and a tail. Bracket syntax like
+/// [1 2 3] is autoconsed into [1 [2 3]] by the Parser and printed back
+/// that way by Display but the Noun itself is always a binary tree.
+///
+/// Nouns are immutable and Cells are reference counted. Cloning a Noun
+/// never copies a tree, it shares it.
+#[derive(Debug,Clone)]
 pub enum Noun {
-    Atom(u64),
-    Cell(Vec<Noun>),
+    Atom(Atom),
+    Cell(Rc<Cell>),
 }
 
+/// Cell is the shared pair behind a Noun::Cell.
+#[derive(Debug)]
+pub struct Cell {
+    head: Noun,
+    tail: Noun,
+    interned: Option<InternId>,
+    // 0 until the mug has been computed. A mug is never 0.
+    mug: cell::Cell<u32>,
+}
+
+impl Cell {
+    /// cached_mug returns the mug of the Cell if it has been computed.
+    pub fn cached_mug(&self) -> Option<u32> {
+        match self.mug.get() {
+            0 => None,
+            mug => Some(mug),
+        }
+    }
+
+    pub(crate) fn cache_mug(&self, mug: u32) {
+        self.mug.set(mug);
+    }
+
+    /// interned returns the id of the Cell if it was interned by an Arena.
+    pub fn interned(&self) -> Option<InternId> {
+        self.interned
+    }
+
+    /// head returns the head of the Cell.
+    pub fn head(&self) -> &Noun {
+        &self.head
+    }
+
+    /// tail returns the tail of the Cell.
+    pub fn tail(&self) -> &Noun {
+        &self.tail
+    }
+}
+
+// Dropping a long list recursively would overflow the stack so we unlink
+// uniquely owned cells onto an explicit stack instead.
+impl Drop for Cell {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        stack.push(mem::replace(&mut self.head, Noun::Atom(Atom::Direct(0))));
+        stack.push(mem::replace(&mut self.tail, Noun::Atom(Atom::Direct(0))));
+        while let Some(noun) = stack.pop() {
+            if let Noun::Cell(rc) = noun {
+                if let Ok(mut cell) = Rc::try_unwrap(rc) {
+                    stack.push(mem::replace(&mut cell.head, Noun::Atom(Atom::Direct(0))));
+                    stack.push(mem::replace(&mut cell.tail, Noun::Atom(Atom::Direct(0))));
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for Noun {
+    fn eq(&self, other: &Noun) -> bool {
+        // Walk both trees with an explicit stack so deep nouns can't
+        // overflow the native stack.
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            match (a, b) {
+                (&Noun::Atom(ref a), &Noun::Atom(ref b)) => {
+                    if a != b {
+                        return false;
+                    }
+                }
+                (&Noun::Cell(ref a), &Noun::Cell(ref b)) => {
+                    // Shared subtrees are equal without walking them.
+                    if Rc::ptr_eq(a, b) {
+                        continue;
+                    }
+                    // An Arena never holds two equal cells so two different
+                    // cells from the same Arena are never equal.
+                    if let (Some(a_id), Some(b_id)) = (a.interned, b.interned) {
+                        if a_id.arena == b_id.arena {
+                            return false;
+                        }
+                    }
+                    // Different mugs mean different nouns.
+                    if let (Some(a_mug), Some(b_mug)) = (a.cached_mug(), b.cached_mug()) {
+                        if a_mug != b_mug {
+                            return false;
+                        }
+                    }
+                    stack.push((&a.tail, &b.tail));
+                    stack.push((&a.head, &b.head));
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Eq for Noun {}
+
 /// atom constructs a Noun::Atom.
 pub fn atom(a: u64) -> Noun {
-    Noun::Atom(a)
+    Noun::Atom(Atom::Direct(a))
 }
 
 impl Display for Noun {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
         match self {
             &Noun::Atom(ref u) => try!(write!(fmt, "{}", u)),
-            &Noun::Cell(ref list) => {
-                let len = list.len();
-                if len > 0 {
-                    try!(write!(fmt, "[{}", list[0]));
-                }
-                if len > 1 {
-                    for n in &list[1..] {
-                        try!(write!(fmt, " {}", n));
-                    }
+            &Noun::Cell(ref cell) => {
+                try!(write!(fmt, "[{}", cell.head));
+                // Autocons the right side of the tree back into a flat list.
+                let mut next = &cell.tail;
+                while let &Noun::Cell(ref cell) = next {
+                    try!(write!(fmt, " {}", cell.head));
+                    next = &cell.tail;
                 }
-                try!(write!(fmt, "]"))
+                try!(write!(fmt, " {}]", next))
             }
         }
         return Ok(());
     }
 }
 
-/// cell! constructs a Noun::Cell.
+/// cell! constructs a Noun::Cell from two or more Nouns.
+/// cell!(a, b, c) is the same as cell!(a, cell!(b, c)).
 #[macro_export]
-macro_rules! cell {    ( $( $x:expr ),* ) => {
-        {
-            let mut temp_vec = Vec::new();
-            $(
-                temp_vec.push($x);
-            )*
-            Noun::Cell(Noun::flatten(temp_vec))
-        }
+macro_rules! cell {
+    ( $x:expr, $y:expr ) => {
+        Noun::cons($x, $y)
+    };
+    ( $x:expr, $( $rest:expr ),+ ) => {
+        Noun::cons($x, cell!($( $rest ),+))
     };
 }
 
 impl Noun {
-    /// flatten flattens a Vec<Nouns> to implement autocons.
-    /// [0 [1 [2 3]]] become [0 1 2 3].
-    /// but [0 [1 2] 3] stays the same.
-    pub fn flatten(mut nouns: Vec<Noun>) -> Vec<Noun> {
-        if nouns.len() >= 1 {
-            let noun = nouns.pop();
-            if let Some(Noun::Cell(mut list)) = noun {
-                let head = list.pop().unwrap();
-                if list.len() > 0 {
-                    nouns.append(&mut list);
-                }
-                nouns.push(head);
-            } else if let Some(noun) = noun {
-                nouns.push(noun);
-            }
+    /// cons constructs a Noun::Cell from a head and a tail.
+    pub fn cons(head: Noun, tail: Noun) -> Noun {
+        Noun::Cell(Rc::new(Cell {
+            head: head,
+            tail: tail,
+            interned: None,
+            mug: cell::Cell::new(0),
+        }))
+    }
+
+    /// cons_interned constructs a Noun::Cell owned by an Arena.
+    pub(crate) fn cons_interned(head: Noun, tail: Noun, id: InternId) -> Noun {
+        Noun::Cell(Rc::new(Cell {
+            head: head,
+            tail: tail,
+            interned: Some(id),
+            mug: cell::Cell::new(0),
+        }))
+    }
+
+    /// is_interned returns true if the Noun is canonical in an Arena.
+    ///
+    /// Atoms are plain values and are always considered interned. A Cell is
+    /// interned only if it was produced by an Arena.
+    pub fn is_interned(&self) -> bool {
+        match self {
+            &Noun::Atom(_) => true,
+            &Noun::Cell(ref cell) => cell.interned.is_some(),
         }
-        return nouns;
+    }
+
+    /// as_cell returns the head and tail of a Noun::Cell or None for an Atom.
+    pub fn as_cell(&self) -> Option<(&Noun, &Noun)> {
+        if let &Noun::Cell(ref cell) = self {
+            return Some((&cell.head, &cell.tail));
+        }
+        None
     }
 
     /// head returns the head of a Noun::Cell or crashes if it's not a Cell.
     pub fn head(&self) -> Result<&Noun, ParseError> {
-        if let &Noun::Cell(ref list) = self {
-            if list.len() > 0 {
-                return Ok(&list[0]);
-            }
+        if let &Noun::Cell(ref cell) = self {
+            return Ok(&cell.head);
         }
-        Err(ParseError::new("!! Atoms or ~ have no head"))
+        Err(ParseError::new("!! Atoms have no head"))
     }
 
     /// tail returns the tail of a Noun::Cell or crashes if it's not a cell.
-    pub fn tail(&self) -> Result<&[Noun], ParseError> {
-        if let &Noun::Cell(ref list) = self {
-            if list.len() > 1 {
-                return Ok(&list[1..]);
-            }
+    pub fn tail(&self) -> Result<&Noun, ParseError> {
+        if let &Noun::Cell(ref cell) = self {
+            return Ok(&cell.tail);
         }
-        Err(ParseError::new("!! Atoms or cells of (len < 2) have no tail"))
+        Err(ParseError::new("!! Atoms have no tail"))
     }
 }
 
@@ -129,7 +253,7 @@ impl Parser {
     }
 
     fn parse_atom(&mut self, tok: &Token) -> Result<Noun, ParseError> {
-        let atom = u64::from_str(&tok.val);
+        let atom = Atom::from_str(&tok.val);
         return match atom {
             Ok(atom) => Ok(Noun::Atom(atom)),
             Err(e) => Err(ParseError::new_with_cause("Atom ParseError", Box::new(e))),
@@ -146,11 +270,18 @@ impl Parser {
             } else if tok.is_cell_start() {
                 list.push(try!(self.parse_cell()))
             } else if tok.is_cell_end() {
-                list = Noun::flatten(list);
                 break;
             }
         }
-        Ok(Noun::Cell(list))
+        if list.len() < 2 {
+            return Err(ParseError::new("!! Cells must have at least 2 Nouns"));
+        }
+        // autocons: [a b c] is [a [b c]]
+        let mut noun = list.pop().unwrap();
+        while let Some(head) = list.pop() {
+            noun = Noun::cons(head, noun);
+        }
+        Ok(noun)
     }
 
     /// Parses a single Noun from the ExpressionReader or returns an error.
@@ -167,6 +298,7 @@ impl Parser {
 
 #[cfg(test)]
 mod parser_tests {
+    use std::rc::Rc;
     use parser::{Parser, Noun, atom};
     use tokenizer::tokenizer_tests::MockReader;
 
@@ -179,7 +311,19 @@ mod parser_tests {
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
-        assert_eq!(noun, Noun::Atom(1));
+        assert_eq!(noun, atom(1));
+    }
+
+    #[test]
+    fn test_parse_big_atom() {
+        let reader = MockReader::new(vec![
+            "340.282.366.920.938.463.463.374.607.431.768.211.456".to_string(),
+        ]);
+        let mut parser = Parser::new(Box::new(reader));
+        let noun = parser.parse();
+        assert!(noun.is_ok());
+        let noun = noun.unwrap();
+        assert_eq!(format!("{}", noun), "340282366920938463463374607431768211456");
     }
 
     #[test]
@@ -191,7 +335,7 @@ mod parser_tests {
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
-        assert_eq!(noun, Noun::Cell(vec![Noun::Atom(1), Noun::Atom(2)]));
+        assert_eq!(noun, Noun::cons(atom(1), atom(2)));
     }
 
     #[test]
@@ -203,8 +347,8 @@ mod parser_tests {
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
-        assert_eq!(noun,
-                   Noun::Cell(vec![Noun::Atom(1), Noun::Atom(2), Noun::Atom(3)]));
+        assert_eq!(noun, cell!(atom(1), cell!(atom(2), atom(3))));
+        assert_eq!(format!("{}", noun), "[1 2 3]");
     }
 
     #[test]
@@ -216,8 +360,8 @@ mod parser_tests {
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
-        assert_eq!(noun,
-                   Noun::Cell(vec![Noun::Atom(1), Noun::Atom(2), Noun::Atom(3)]));
+        assert_eq!(noun, cell!(atom(1), cell!(atom(2), atom(3))));
+        assert_eq!(format!("{}", noun), "[1 2 3]");
     }
 
     #[test]
@@ -229,14 +373,12 @@ mod parser_tests {
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
-        assert_eq!(noun,
-                   Noun::Cell(vec![Noun::Atom(1),
-                                   Noun::Cell(vec![Noun::Atom(2), Noun::Atom(3)]),
-                                   Noun::Atom(4)]));
+        assert_eq!(noun, cell!(atom(1), cell!(atom(2), atom(3)), atom(4)));
+        assert_eq!(format!("{}", noun), "[1 [2 3] 4]");
     }
 
     #[test]
-    fn test_flatten() {
+    fn test_parse_autocons_nested_cells() {
         let reader = MockReader::new(vec![
             "[[1 2] [1 2]]".to_string(),
             "[[1 [2 3] 4] [1 [2 3] 4]]".to_string(),
@@ -245,14 +387,45 @@ mod parser_tests {
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
-        assert_eq!(noun,
-                  Noun::Cell(vec![Noun::Cell(vec![atom(1), atom(2)]),
-                                  atom(1), atom(2)]));
+        assert_eq!(noun, cell!(cell!(atom(1), atom(2)), atom(1), atom(2)));
+        assert_eq!(format!("{}", noun), "[[1 2] 1 2]");
         let noun = parser.parse();
         assert!(noun.is_ok());
         let noun = noun.unwrap();
         assert_eq!(noun,
-                  Noun::Cell(vec![Noun::Cell(vec![atom(1), Noun::Cell(vec![atom(2), atom(3)]), atom(4)]),
-                                  atom(1), Noun::Cell(vec![atom(2), atom(3)]), atom(4)]));
+                   cell!(cell!(atom(1), cell!(atom(2), atom(3)), atom(4)),
+                         atom(1), cell!(atom(2), atom(3)), atom(4)));
+        assert_eq!(format!("{}", noun), "[[1 [2 3] 4] 1 [2 3] 4]");
+    }
+
+    #[test]
+    fn test_parse_single_noun_cell_fails() {
+        let reader = MockReader::new(vec![
+            "[1]".to_string(),
+        ]);
+        let mut parser = Parser::new(Box::new(reader));
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_head_and_tail() {
+        let noun = cell!(atom(1), atom(2), atom(3));
+        assert_eq!(noun.head().unwrap(), &atom(1));
+        assert_eq!(noun.tail().unwrap(), &cell!(atom(2), atom(3)));
+        assert!(atom(1).head().is_err());
+        assert!(atom(1).tail().is_err());
+    }
+
+    #[test]
+    fn test_clone_shares_cells() {
+        let noun = cell!(atom(1), cell!(atom(2), atom(3)));
+        let copy = noun.clone();
+        if let (&Noun::Cell(ref a), &Noun::Cell(ref b)) = (&noun, &copy) {
+            assert!(Rc::ptr_eq(a, b));
+        } else {
+            panic!("Expected cells");
+        }
+        assert_eq!(noun, copy);
+        assert!(noun != cell!(atom(1), cell!(atom(2), atom(4))));
     }
 }
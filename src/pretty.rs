@@ -0,0 +1,192 @@
+//! pretty implements a width aware pretty printer for nouns.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use aura::Aura;
+use parser::Noun;
+
+/// Printer renders nouns across multiple lines.
+///
+/// A cell that fits in the remaining width is printed on one line just like
+/// Display would print it. A cell that doesn't fit is broken with each of
+/// its autoconsed elements on its own line, indented one column past the
+/// opening bracket:
+///
+/// ```text
+/// [1
+///  [2 3]
+///  4]
+/// ```
+///
+/// Cells nested deeper than max_depth are elided as `...`. Atoms are printed
+/// in decimal unless an aura is set.
+#[derive(Clone)]
+pub struct Printer {
+    width: usize,
+    max_depth: Option<usize>,
+    aura: Option<Aura>,
+}
+
+// items returns the autoconsed elements of a cell. [1 2 3] is [1 [2 3]] so
+// its items are 1, 2 and 3.
+fn items(noun: &Noun) -> Vec<&Noun> {
+    let mut items = Vec::new();
+    let mut next = noun;
+    while let Some((head, tail)) = next.as_cell() {
+        items.push(head);
+        next = tail;
+    }
+    items.push(next);
+    items
+}
+
+impl Printer {
+    /// new constructs a Printer that breaks lines at width and elides
+    /// cells deeper than max_depth.
+    pub fn new(width: usize, max_depth: Option<usize>) -> Self {
+        Printer {
+            width: width,
+            max_depth: max_depth,
+            aura: None,
+        }
+    }
+
+    /// set_aura renders atoms in aura. None restores plain decimal.
+    pub fn set_aura(&mut self, aura: Option<Aura>) {
+        self.aura = aura;
+    }
+
+    /// aura returns the aura atoms are rendered in.
+    pub fn aura(&self) -> Option<Aura> {
+        self.aura
+    }
+
+    /// render returns the pretty printed noun.
+    pub fn render(&self, noun: &Noun) -> String {
+        let mut out = String::new();
+        self.pretty(noun, 0, 0, &mut out);
+        out
+    }
+
+    fn elided(&self, depth: usize) -> bool {
+        self.max_depth.map_or(false, |max| depth >= max)
+    }
+
+    // flat renders the noun on a single line. It stops and returns false as
+    // soon as the output grows past limit so measuring a huge noun is cheap.
+    fn flat(&self, noun: &Noun, depth: usize, limit: usize, out: &mut String) -> bool {
+        match noun {
+            &Noun::Atom(ref a) => {
+                match self.aura {
+                    Some(aura) => out.push_str(&aura.render(a)),
+                    None => out.push_str(&a.to_string()),
+                }
+            }
+            &Noun::Cell(_) => {
+                if self.elided(depth) {
+                    out.push_str("...");
+                } else {
+                    out.push('[');
+                    for (i, item) in items(noun).into_iter().enumerate() {
+                        if i > 0 {
+                            out.push(' ');
+                        }
+                        if !self.flat(item, depth + 1, limit, out) {
+                            return false;
+                        }
+                    }
+                    out.push(']');
+                }
+            }
+        }
+        out.len() <= limit
+    }
+
+    // pretty renders the noun starting at column indent.
+    //
+    // Only the last item of a cell is followed by closing brackets and
+    // autocons means it is always an atom which never breaks anyway.
+    fn pretty(&self, noun: &Noun, depth: usize, indent: usize, out: &mut String) {
+        let limit = self.width.saturating_sub(indent);
+        let mut flat = String::new();
+        if self.flat(noun, depth, limit, &mut flat) || noun.as_cell().is_none() ||
+           self.elided(depth) {
+            out.push_str(&flat);
+            return;
+        }
+        out.push('[');
+        for (i, item) in items(noun).into_iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+                for _ in 0..indent + 1 {
+                    out.push(' ');
+                }
+            }
+            self.pretty(item, depth + 1, indent + 1, out);
+        }
+        out.push(']');
+    }
+}
+
+#[cfg(test)]
+mod pretty_tests {
+    use aura::Aura;
+    use pretty::Printer;
+    use parser::{Noun, atom};
+
+    #[test]
+    fn test_fits_on_one_line() {
+        let noun = cell!(atom(1), cell!(atom(2), atom(3)), atom(4));
+        assert_eq!(Printer::new(80, None).render(&noun), format!("{}", noun));
+        assert_eq!(Printer::new(80, None).render(&atom(42)), "42");
+    }
+
+    #[test]
+    fn test_breaks_at_width() {
+        let noun = cell!(atom(1), cell!(atom(22), atom(33)), atom(4));
+        assert_eq!(Printer::new(10, None).render(&noun), "[1\n [22 33]\n 4]");
+        let nested = cell!(cell!(atom(111), atom(222), atom(333)), atom(4));
+        assert_eq!(Printer::new(10, None).render(&nested),
+                   "[[111\n  222\n  333]\n 4]");
+    }
+
+    #[test]
+    fn test_elides_deep_cells() {
+        let noun = cell!(atom(1), cell!(cell!(atom(3), atom(4)), atom(2)), atom(5));
+        assert_eq!(Printer::new(80, Some(3)).render(&noun), "[1 [[3 4] 2] 5]");
+        assert_eq!(Printer::new(80, Some(2)).render(&noun), "[1 [... 2] 5]");
+        assert_eq!(Printer::new(80, Some(1)).render(&noun), "[1 ... 5]");
+        assert_eq!(Printer::new(80, Some(0)).render(&noun), "...");
+    }
+
+    #[test]
+    fn test_atoms_in_aura() {
+        let noun = cell!(atom(255), atom(0x6f6c6c6568));
+        let mut printer = Printer::new(80, None);
+        printer.set_aura(Some(Aura::Ux));
+        assert_eq!(printer.render(&noun), "[0xff 0x6f.6c6c.6568]");
+        printer.set_aura(Some(Aura::Auto));
+        assert_eq!(printer.render(&noun), "[255 %hello]");
+    }
+
+    #[test]
+    fn test_huge_noun_is_measured_lazily() {
+        let mut list = atom(0);
+        for i in 0..10000 {
+            list = Noun::cons(atom(i), list);
+        }
+        let rendered = Printer::new(80, None).render(&list);
+        assert_eq!(rendered.lines().count(), 10001);
+        assert!(rendered.lines().all(|l| l.len() <= 80));
+    }
+}
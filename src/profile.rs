@@ -0,0 +1,193 @@
+//! profile counts where an interpreter spends its reductions.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use parser::Noun;
+use pretty::Printer;
+
+/// OPCODES is the number of nock instructions a Profile counts.
+pub const OPCODES: usize = 13;
+
+/// Profile counts the reductions of every computation an interpreter makes
+/// while profiling. It accumulates across computations.
+pub struct Profile {
+    steps: u64,
+    opcodes: [u64; OPCODES],
+    // Autocons and unknown opcodes.
+    cons: u64,
+    other: u64,
+    // Formulas keyed by mug with how often each was reduced.
+    formulas: HashMap<u32, (Noun, u64)>,
+    // Jets by name with their calls and time spent.
+    jets: HashMap<String, (u64, Duration)>,
+    max_depth: usize,
+}
+
+impl Profile {
+    /// new constructs an empty Profile.
+    pub fn new() -> Self {
+        Profile {
+            steps: 0,
+            opcodes: [0; OPCODES],
+            cons: 0,
+            other: 0,
+            formulas: HashMap::new(),
+            jets: HashMap::new(),
+            max_depth: 0,
+        }
+    }
+
+    /// step records a reduction of formula with depth computations waiting
+    /// on it.
+    pub fn step(&mut self, depth: usize, formula: &Noun) {
+        self.steps += 1;
+        if depth > self.max_depth {
+            self.max_depth = depth;
+        }
+        match formula.as_cell() {
+            Some((&Noun::Atom(ref op), _)) => {
+                match op.as_u64() {
+                    Some(op) if (op as usize) < OPCODES => self.opcodes[op as usize] += 1,
+                    _ => self.other += 1,
+                }
+            }
+            Some(_) => self.cons += 1,
+            None => self.other += 1,
+        }
+        self.formulas.entry(formula.mug()).or_insert_with(|| (formula.clone(), 0)).1 += 1;
+    }
+
+    /// jet records a call of the jet name that took elapsed.
+    pub fn jet(&mut self, name: &str, elapsed: Duration) {
+        let entry = self.jets.entry(name.to_string()).or_insert((0, Duration::new(0, 0)));
+        entry.0 += 1;
+        entry.1 += elapsed;
+    }
+
+    /// steps returns the number of reductions recorded.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// opcode returns the number of reductions of formulas with opcode op.
+    pub fn opcode(&self, op: usize) -> u64 {
+        self.opcodes[op]
+    }
+
+    /// max_depth returns the most computations that were waiting on a
+    /// reduction.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// jet_calls returns the number of calls of the jet name.
+    pub fn jet_calls(&self, name: &str) -> u64 {
+        self.jets.get(name).map_or(0, |&(calls, _)| calls)
+    }
+
+    /// top returns the n most reduced formulas with their mugs and counts.
+    /// Formulas are told apart by their mug.
+    pub fn top(&self, n: usize) -> Vec<(u32, &Noun, u64)> {
+        let mut formulas: Vec<(u32, &Noun, u64)> =
+            self.formulas.iter().map(|(mug, &(ref formula, count))| (*mug, formula, count)).collect();
+        formulas.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        formulas.truncate(n);
+        formulas
+    }
+
+    /// report renders the profile with the top formulas most reduced
+    /// formulas printed by printer.
+    pub fn report(&self, top: usize, printer: &Printer) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("steps: {}\n", self.steps));
+        out.push_str(&format!("deepest recursion: {} frames\n", self.max_depth));
+        out.push_str("opcodes:\n");
+        for (op, count) in self.opcodes.iter().enumerate() {
+            if *count > 0 {
+                out.push_str(&format!("  {:<6} {:>10} {:>6.1}%\n", op, count, self.percent(*count)));
+            }
+        }
+        for &(name, count) in [("cons", self.cons), ("other", self.other)].iter() {
+            if count > 0 {
+                out.push_str(&format!("  {:<6} {:>10} {:>6.1}%\n", name, count, self.percent(count)));
+            }
+        }
+        if !self.jets.is_empty() {
+            out.push_str("jets:\n");
+            let mut jets: Vec<(&String, &(u64, Duration))> = self.jets.iter().collect();
+            jets.sort_by(|a, b| (b.1).1.cmp(&(a.1).1).then(a.0.cmp(b.0)));
+            for (name, &(calls, elapsed)) in jets {
+                out.push_str(&format!("  {:<6} {:>10} calls {:>10.3}ms\n", name, calls, millis(elapsed)));
+            }
+        }
+        out.push_str("top formulas:\n");
+        for (mug, formula, count) in self.top(top) {
+            out.push_str(&format!("  {:>10} {:08x}  {}\n", count, mug, printer.render(formula)));
+        }
+        out
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.steps as f64
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1000000.0
+}
+
+#[cfg(test)]
+mod profile_tests {
+    use std::time::Duration;
+    use parser::{Noun, atom};
+    use pretty::Printer;
+    use profile::Profile;
+
+    #[test]
+    fn test_profile() {
+        let mut profile = Profile::new();
+        let slot = cell!(atom(0), atom(1));
+        profile.step(0, &cell!(atom(4), atom(0), atom(1)));
+        profile.step(1, &slot);
+        profile.step(0, &cell!(slot.clone(), slot.clone()));
+        profile.step(1, &slot);
+        profile.step(1, &slot);
+        profile.step(0, &cell!(atom(99), atom(0)));
+        profile.jet("dec", Duration::new(0, 1500000));
+        profile.jet("dec", Duration::new(0, 500000));
+        assert_eq!(profile.steps(), 6);
+        assert_eq!(profile.opcode(0), 3);
+        assert_eq!(profile.opcode(4), 1);
+        assert_eq!(profile.max_depth(), 1);
+        assert_eq!(profile.jet_calls("dec"), 2);
+        assert_eq!(profile.top(1), vec![(slot.mug(), &slot, 3)]);
+        let report = profile.report(1, &Printer::new(80, None));
+        assert_eq!(report,
+                   format!("steps: 6\n\
+                            deepest recursion: 1 frames\n\
+                            opcodes:\n  \
+                            0               3   50.0%\n  \
+                            4               1   16.7%\n  \
+                            cons            1   16.7%\n  \
+                            other           1   16.7%\n\
+                            jets:\n  \
+                            dec             2 calls      2.000ms\n\
+                            top formulas:\n  \
+                            {:>10} {:08x}  [0 1]\n",
+                           3,
+                           slot.mug()));
+    }
+}
@@ -0,0 +1,136 @@
+//! scry implements the namespaces nock 12 reads from.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use jam;
+use parser::Noun;
+
+/// Namespace resolves the reads made by nock 12.
+///
+/// A namespace must be referentially transparent. Reading the same ref and
+/// path twice has to return the same noun.
+pub trait Namespace {
+    /// scry returns the noun bound to path or None if the read should
+    /// block the computation.
+    fn scry(&self, reference: &Noun, path: &Noun) -> Option<Noun>;
+}
+
+/// Blocking is the namespace with nothing in it. Every read blocks.
+pub struct Blocking;
+
+impl Namespace for Blocking {
+    fn scry(&self, _: &Noun, _: &Noun) -> Option<Noun> {
+        None
+    }
+}
+
+/// JamDir is a namespace backed by a directory of jammed files.
+///
+/// The path is a null terminated list of cords naming a file below the root
+/// with a .jam extension so /foo/bar is read from root/foo/bar.jam. The ref
+/// is ignored. Reads of a missing or malformed file block.
+pub struct JamDir {
+    root: PathBuf,
+}
+
+impl JamDir {
+    /// new constructs a JamDir reading files below root.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        JamDir { root: root.into() }
+    }
+
+    // file returns the file a path names or None if the path isn't a list of
+    // plain file names.
+    fn file(&self, path: &Noun) -> Option<PathBuf> {
+        let segments = match Vec::<String>::try_from(path.clone()) {
+            Ok(segments) => segments,
+            Err(_) => return None,
+        };
+        if segments.is_empty() {
+            return None;
+        }
+        let mut file = self.root.clone();
+        let last = segments.len() - 1;
+        for (i, segment) in segments.into_iter().enumerate() {
+            if segment.is_empty() || segment == "." || segment == ".." ||
+               segment.contains('/') || segment.contains('\\') {
+                return None;
+            }
+            // The last segment keeps any dots it has and gains .jam.
+            if i == last {
+                file.push(format!("{}.jam", segment));
+            } else {
+                file.push(segment);
+            }
+        }
+        Some(file)
+    }
+}
+
+impl Namespace for JamDir {
+    fn scry(&self, _: &Noun, path: &Noun) -> Option<Noun> {
+        let file = match self.file(path) {
+            Some(file) => file,
+            None => return None,
+        };
+        let mut bytes = Vec::new();
+        if File::open(file).and_then(|mut f| f.read_to_end(&mut bytes)).is_err() {
+            return None;
+        }
+        jam::cue_bytes(&bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod scry_tests {
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+    use jam;
+    use parser::{Noun, atom};
+    use scry::{Blocking, JamDir, Namespace};
+
+    #[test]
+    fn test_blocking() {
+        assert_eq!(Blocking.scry(&atom(0), &Noun::from(vec!["foo"])), None);
+    }
+
+    #[test]
+    fn test_jam_dir() {
+        let root = env::temp_dir().join(format!("nock-scry-test-{}", ::std::process::id()));
+        fs::create_dir_all(root.join("foo")).unwrap();
+        let noun = cell!(atom(1), atom(2), atom(3));
+        File::create(root.join("foo").join("bar.jam"))
+            .and_then(|mut f| f.write_all(&jam::jam_bytes(&noun)))
+            .unwrap();
+        let ns = JamDir::new(root.clone());
+        assert_eq!(ns.scry(&atom(0), &Noun::from(vec!["foo", "bar"])), Some(noun));
+        assert_eq!(ns.scry(&atom(0), &Noun::from(vec!["foo", "baz"])), None);
+        File::create(root.join("foo").join("v1.2.jam"))
+            .and_then(|mut f| f.write_all(&jam::jam_bytes(&atom(12))))
+            .unwrap();
+        assert_eq!(ns.scry(&atom(0), &Noun::from(vec!["foo", "v1.2"])), Some(atom(12)));
+        assert_eq!(ns.scry(&atom(0), &Noun::from(vec!["foo", "v1"])), None);
+        assert_eq!(ns.scry(&atom(0), &Noun::from(vec!["foo", "bar.txt"])), None);
+        assert_eq!(ns.scry(&atom(0), &Noun::from(vec!["..", "foo", "bar"])), None);
+        assert_eq!(ns.scry(&atom(0), &atom(0)), None);
+        assert_eq!(ns.scry(&atom(0), &atom(42)), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! trace implements observing each reduction an interpreter makes.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::io::Write;
+use std::usize;
+
+use parser::Noun;
+use pretty::Printer;
+
+/// Tracer observes the reductions an interpreter makes.
+///
+/// depth is the number of computations waiting on the product of the one
+/// being traced. Once the test of 6 or the subject of 7 or 8 is computed the
+/// formula it selects replaces the macro so it is traced at the same depth.
+pub trait Tracer {
+    /// step is called before formula is reduced against subject by rule.
+    fn step(&mut self, depth: usize, subject: &Noun, formula: &Noun, rule: &str);
+
+    /// product is called when the computation at depth produces a noun.
+    fn product(&mut self, depth: usize, product: &Noun);
+}
+
+/// TraceWriter writes each reduction in the spec's notation.
+///
+/// ```text
+/// *[42 4 0 1]  :: *[a 4 b] -> +*[a b]
+///   *[42 0 1]  :: *[a 0 b] -> /[b a]
+///   -> 42
+/// -> 43
+/// ```
+pub struct TraceWriter<W: Write> {
+    out: W,
+    max_depth: Option<usize>,
+    printer: Printer,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// new constructs a TraceWriter that skips computations deeper than
+    /// max_depth and elides cells in subjects, formulas and products nested
+    /// deeper than elide_depth.
+    pub fn new(out: W, max_depth: Option<usize>, elide_depth: Option<usize>) -> Self {
+        TraceWriter {
+            out: out,
+            max_depth: max_depth,
+            printer: Printer::new(usize::MAX, elide_depth),
+        }
+    }
+
+    /// into_inner returns the writer the trace was written to.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn skipped(&self, depth: usize) -> bool {
+        self.max_depth.map_or(false, |max| depth > max)
+    }
+}
+
+fn indent(depth: usize) -> String {
+    let mut indent = String::new();
+    for _ in 0..depth {
+        indent.push_str("  ");
+    }
+    indent
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn step(&mut self, depth: usize, subject: &Noun, formula: &Noun, rule: &str) {
+        if self.skipped(depth) {
+            return;
+        }
+        // The formula is autoconsed onto the subject so [4 0 1] is written
+        // *[a 4 0 1] like the spec does.
+        let formula = self.printer.render(formula);
+        let formula = if formula.starts_with('[') {
+            &formula[1..formula.len() - 1]
+        } else {
+            &formula[..]
+        };
+        let _ = writeln!(self.out,
+                         "{}*[{} {}]  :: {}",
+                         indent(depth),
+                         self.printer.render(subject),
+                         formula,
+                         rule);
+    }
+
+    fn product(&mut self, depth: usize, product: &Noun) {
+        if self.skipped(depth) {
+            return;
+        }
+        let _ = writeln!(self.out, "{}-> {}", indent(depth), self.printer.render(product));
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use parser::{Noun, atom};
+    use trace::{TraceWriter, Tracer};
+
+    #[test]
+    fn test_trace_writer() {
+        let subject = cell!(cell!(atom(1), atom(2)), atom(3));
+        let formula = cell!(atom(4), atom(0), atom(1));
+        let mut writer = TraceWriter::new(Vec::new(), Some(1), Some(1));
+        writer.step(0, &subject, &formula, "*[a 4 b] -> +*[a b]");
+        writer.step(1, &atom(42), &cell!(atom(0), atom(1)), "*[a 0 b] -> /[b a]");
+        writer.step(2, &atom(42), &cell!(atom(0), atom(1)), "*[a 0 b] -> /[b a]");
+        writer.product(2, &atom(42));
+        writer.product(1, &atom(42));
+        writer.product(0, &atom(43));
+        let trace = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(trace,
+                   "*[[... 3] 4 0 1]  :: *[a 4 b] -> +*[a b]\n  \
+                    *[42 0 1]  :: *[a 0 b] -> /[b a]\n  \
+                    -> 42\n\
+                    -> 43\n");
+    }
+
+    #[test]
+    fn test_trace_writer_elides() {
+        let deep = cell!(cell!(cell!(cell!(atom(1), atom(2)), atom(3)), atom(4)), atom(5));
+        let mut writer = TraceWriter::new(Vec::new(), None, Some(2));
+        writer.step(0, &atom(42), &cell!(atom(1), deep.clone()), "*[a 1 b] -> b");
+        writer.product(0, &deep);
+        let trace = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(trace,
+                   "*[42 1 [... 4] 5]  :: *[a 1 b] -> b\n\
+                    -> [[... 4] 5]\n");
+    }
+}
@@ -0,0 +1,802 @@
+//! vm compiles nock formulas to bytecode and runs them.
+// Copyright (2017) Jeremy A. Wall.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cmp;
+use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+use atom::Atom;
+use bail::{Bail, Exit};
+use crash::Hint;
+use jets::Mismatch;
+use nock::{self, Computed, Interpreter, Limits, NockError, Spec};
+use parser::{Noun, atom};
+
+/// Op is a bytecode instruction. Instructions work on a stack of values and
+/// a subject register.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// Pushes /[axis subject].
+    Slot(Atom),
+    /// Pushes a noun.
+    Const(Noun),
+    /// Pops a tail and a head and pushes the cell of them.
+    Cons,
+    /// Pops a noun and pushes 0 if it's a cell or 1 if it's an atom.
+    Wut,
+    /// Pops an atom and pushes its increment.
+    Lus,
+    /// Pops two nouns and pushes 0 if they're equal or 1 if they're not.
+    Tis,
+    /// Pops a cell and pushes 0 if its head and tail are equal or 1 if
+    /// they're not.
+    TisCell,
+    /// Pops a formula and a subject and computes the formula against the
+    /// subject.
+    Eval,
+    /// Eval in tail position. The computation replaces the running code
+    /// instead of returning to it.
+    TailEval,
+    /// Pops a noun and makes it the subject saving the old one.
+    Enter,
+    /// Pops a noun and makes [noun subject] the subject saving the old one.
+    Pin,
+    /// Restores the subject saved by the last Enter or Pin.
+    Leave,
+    /// Pops a loobean and jumps to the address if it's 1.
+    Branch(usize),
+    /// Jumps to the address.
+    Jump(usize),
+    /// Pops a core and computes its arm at axis against it.
+    Call(Atom),
+    /// Call in tail position. The arm replaces the running code instead of
+    /// returning to it.
+    TailCall(Atom),
+    /// Pops a target and a value and pushes #[axis value target].
+    Edit(Atom),
+    /// Pops a noun.
+    Pop,
+    /// Pops a core and the %fast clue naming it and pushes the core.
+    Fast,
+    /// Pushes the cached product of the formula against the subject and
+    /// jumps to the address if there is one.
+    MemoGet(Noun, usize),
+    /// Caches the noun on top of the stack as the product of the formula
+    /// against the subject.
+    MemoPut(Noun),
+    /// Pops a clue onto the trace stack.
+    TracePush(Hint),
+    /// Pops the trace stack.
+    TracePop,
+    /// Pops a path and a ref and pushes the namespace's answer.
+    Scry,
+    /// Crashes. Malformed formulas compile to a crash so they only crash if
+    /// they're reached.
+    Crash(Exit),
+}
+
+/// Code is a compiled formula. Running it against a subject leaves the
+/// product on the stack.
+#[derive(Debug)]
+pub struct Code {
+    ops: Vec<Op>,
+}
+
+impl Code {
+    /// ops returns the instructions of the code.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+}
+
+// Task is a piece of compiling left to do. The compiler keeps them on a
+// stack instead of recursing so deep formulas can't overflow the native
+// stack.
+enum Task {
+    Compile(Noun),
+    Emit(Op),
+    // Emits a Branch for Else to point past the then formula.
+    Branch,
+    // Emits a Jump for End to point past the else formula and points the
+    // last Branch here.
+    Else,
+    // Emits a MemoGet for End to point past the memoized formula.
+    MemoGet(Noun),
+    // Points the last Jump or MemoGet here.
+    End,
+}
+
+/// compile compiles a formula to Code with the rules of spec.
+///
+/// * 0, 1, 3, 4 and 5 compile to the instruction computing them after the
+///   instructions computing their arguments.
+/// * 2 computes the new subject and formula then Evals.
+/// * 6 Branches over the then formula and Jumps over the else formula.
+/// * 7 and 8 Enter or Pin the new subject around c.
+/// * 9 computes the core and Calls the arm.
+/// * 11 hints compile to the instructions that act on them around d.
+///
+/// An Eval or Call that's only followed by Leaves and Jumps to the end of the
+/// code is in tail position and becomes a TailEval or TailCall. Loops
+/// written as recursive calls through 2 or 9 then run in constant stack.
+pub fn compile(formula: &Noun, spec: Spec) -> Code {
+    let mut ops = Vec::new();
+    let mut pending = Vec::new();
+    let mut tasks = vec![Task::Compile(formula.clone())];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Compile(formula) => {
+                // Tasks run last in first out.
+                tasks.extend(expand(formula, spec).into_iter().rev());
+            }
+            Task::Emit(op) => ops.push(op),
+            Task::Branch => {
+                pending.push(ops.len());
+                ops.push(Op::Branch(0));
+            }
+            Task::Else => {
+                let branch = pending.pop().expect("Else without a Branch");
+                pending.push(ops.len());
+                ops.push(Op::Jump(0));
+                ops[branch] = Op::Branch(ops.len());
+            }
+            Task::MemoGet(formula) => {
+                pending.push(ops.len());
+                ops.push(Op::MemoGet(formula, 0));
+            }
+            Task::End => {
+                let at = pending.pop().expect("End without a Jump or MemoGet");
+                let end = ops.len();
+                ops[at] = match mem::replace(&mut ops[at], Op::Pop) {
+                    Op::Jump(_) => Op::Jump(end),
+                    Op::MemoGet(formula, _) => Op::MemoGet(formula, end),
+                    op => panic!("Can't point {:?} at the end", op),
+                };
+            }
+        }
+    }
+    for pc in 0..ops.len() {
+        if !returns(&ops, pc + 1) {
+            continue;
+        }
+        ops[pc] = match mem::replace(&mut ops[pc], Op::Pop) {
+            Op::Eval => Op::TailEval,
+            Op::Call(axis) => Op::TailCall(axis),
+            op => op,
+        };
+    }
+    Code { ops: ops }
+}
+
+// returns is true if the code returns from pc without doing anything but
+// restoring subjects.
+fn returns(ops: &[Op], mut pc: usize) -> bool {
+    while pc < ops.len() {
+        match ops[pc] {
+            Op::Leave => pc += 1,
+            // Jumps only go forward.
+            Op::Jump(to) => pc = to,
+            _ => return false,
+        }
+    }
+    true
+}
+
+// expand returns the tasks that compile one formula in order.
+fn expand(formula: Noun, spec: Spec) -> Vec<Task> {
+    let (head, tail) = match formula.as_cell() {
+        Some((head, tail)) => (head.clone(), tail.clone()),
+        None => {
+            return vec![Task::Emit(Op::Crash(Exit::Formula {
+                            opcode: None,
+                            formula: formula,
+                        }))]
+        }
+    };
+    let op = match head {
+        // *[a [b c] d] -> [*[a b c] *[a d]]
+        Noun::Cell(_) => return vec![Task::Compile(head), Task::Compile(tail), Task::Emit(Op::Cons)],
+        Noun::Atom(ref a) => a.as_u64(),
+    };
+    let malformed = |op: u64| {
+        vec![Task::Emit(Op::Crash(Exit::Formula {
+                 opcode: Some(op),
+                 formula: formula.clone(),
+             }))]
+    };
+    let unknown = || {
+        vec![Task::Emit(Op::Crash(Exit::Opcode {
+                 opcode: head.clone(),
+                 formula: formula.clone(),
+             }))]
+    };
+    let op = match op {
+        Some(op) => op,
+        None => return unknown(),
+    };
+    // Every opcode but 0, 1, 3 and 4 takes two arguments.
+    let args = tail.as_cell().map(|(b, c)| (b.clone(), c.clone()));
+    match (op, args) {
+        (0, _) => {
+            match tail {
+                Noun::Atom(b) => vec![Task::Emit(Op::Slot(b))],
+                _ => malformed(op),
+            }
+        }
+        (1, _) => vec![Task::Emit(Op::Const(tail))],
+        (2, Some((b, c))) => vec![Task::Compile(b), Task::Compile(c), Task::Emit(Op::Eval)],
+        (3, _) => vec![Task::Compile(tail), Task::Emit(Op::Wut)],
+        (4, _) => vec![Task::Compile(tail), Task::Emit(Op::Lus)],
+        (5, _) if spec == Spec::Nock5K => vec![Task::Compile(tail), Task::Emit(Op::TisCell)],
+        (5, Some((b, c))) => vec![Task::Compile(b), Task::Compile(c), Task::Emit(Op::Tis)],
+        (6, Some((b, cd))) => {
+            match cd.as_cell() {
+                Some((c, d)) => {
+                    vec![Task::Compile(b),
+                         Task::Branch,
+                         Task::Compile(c.clone()),
+                         Task::Else,
+                         Task::Compile(d.clone()),
+                         Task::End]
+                }
+                None => malformed(op),
+            }
+        }
+        (7, Some((b, c))) => {
+            vec![Task::Compile(b), Task::Emit(Op::Enter), Task::Compile(c), Task::Emit(Op::Leave)]
+        }
+        (8, Some((b, c))) => {
+            vec![Task::Compile(b), Task::Emit(Op::Pin), Task::Compile(c), Task::Emit(Op::Leave)]
+        }
+        (9, Some((Noun::Atom(b), c))) => vec![Task::Compile(c), Task::Emit(Op::Call(b))],
+        (10, Some((bc, d))) if spec == Spec::Nock4K => {
+            match bc.as_cell() {
+                Some((&Noun::Atom(ref b), c)) => {
+                    vec![Task::Compile(c.clone()), Task::Compile(d), Task::Emit(Op::Edit(b.clone()))]
+                }
+                _ => malformed(op),
+            }
+        }
+        (10, Some((b, d))) => {
+            match b.as_cell() {
+                // *[a 10 b c] -> *[a c]
+                None => vec![Task::Compile(d)],
+                // *[a 10 [b c] d] -> *[a 8 c 7 [0 3] d]
+                Some((_, c)) => vec![Task::Compile(c.clone()), Task::Emit(Op::Pop), Task::Compile(d)],
+            }
+        }
+        (11, Some((b, d))) if spec == Spec::Nock4K => hint(b, d),
+        (12, Some((b, c))) if spec == Spec::Nock4K => {
+            vec![Task::Compile(b), Task::Compile(c), Task::Emit(Op::Scry)]
+        }
+        (op, _) if op <= 10 || (op <= 12 && spec == Spec::Nock4K) => malformed(op),
+        _ => unknown(),
+    }
+}
+
+// hint returns the tasks that compile *[a 11 b d].
+fn hint(b: Noun, d: Noun) -> Vec<Task> {
+    let memoized = |d: Noun| {
+        vec![Task::MemoGet(d.clone()), Task::Compile(d.clone()), Task::Emit(Op::MemoPut(d)), Task::End]
+    };
+    let (tag, c) = match b.as_cell() {
+        Some((tag, c)) => (tag.clone(), c.clone()),
+        None if b == atom(nock::MEMO) => return memoized(d),
+        None => return vec![Task::Compile(d)],
+    };
+    if tag == atom(nock::FAST) {
+        return vec![Task::Compile(c), Task::Compile(d), Task::Emit(Op::Fast)];
+    }
+    let mut tasks = vec![Task::Compile(c)];
+    if tag == atom(nock::MEMO) {
+        tasks.push(Task::Emit(Op::Pop));
+        tasks.extend(memoized(d));
+    } else if let Some(hint) = Hint::from_tag(&tag) {
+        tasks.extend(vec![Task::Emit(Op::TracePush(hint)), Task::Compile(d), Task::Emit(Op::TracePop)]);
+    } else {
+        tasks.extend(vec![Task::Emit(Op::Pop), Task::Compile(d)]);
+    }
+    tasks
+}
+
+// Frame is a computation waiting on the code it called.
+struct Frame {
+    code: Rc<Code>,
+    pc: usize,
+    subject: Noun,
+    // The number of saved subjects when the code was called.
+    base: usize,
+    // A jetted gate's name, sample and product to check against its nock.
+    verify: Option<(String, Noun, Noun)>,
+}
+
+// Vm is the state of a running computation.
+struct Vm {
+    code: Rc<Code>,
+    pc: usize,
+    subject: Noun,
+    stack: Vec<Noun>,
+    // Subjects saved by Enter and Pin.
+    subjects: Vec<Noun>,
+    // The number of saved subjects when the running code was called. The
+    // rest belong to it.
+    base: usize,
+    frames: Vec<Frame>,
+    // The most frames and saved subjects there have been at once.
+    depth: usize,
+    trace: Vec<(Hint, Noun)>,
+    steps: u64,
+}
+
+/// run computes a nock expression of type [subj formula] or atom with the
+/// bytecode VM. Every instruction the VM runs is a step.
+pub(crate) fn run(ctx: &mut Interpreter, noun: Noun, limits: Limits) -> Result<Computed, NockError> {
+    if let Some(ref flag) = ctx.interrupt {
+        flag.store(false, Ordering::SeqCst);
+    }
+    let mut vm = Vm::new(ctx, noun);
+    match vm.run(ctx, limits) {
+        Ok(product) => {
+            Ok(Computed {
+                product: product,
+                steps: vm.steps,
+            })
+        }
+        Err(err) => Err(ctx.traced(&vm.trace, err)),
+    }
+}
+
+impl Vm {
+    fn new(ctx: &mut Interpreter, noun: Noun) -> Self {
+        let (subject, formula) = match noun {
+            Noun::Atom(_) => (atom(0), noun),
+            Noun::Cell(cell) => (cell.head().clone(), cell.tail().clone()),
+        };
+        Vm {
+            code: ctx.code(&formula),
+            pc: 0,
+            subject: subject,
+            stack: Vec::new(),
+            subjects: Vec::new(),
+            base: 0,
+            frames: Vec::new(),
+            depth: 0,
+            trace: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Noun {
+        self.stack.pop().expect("The VM stack underflowed")
+    }
+
+    // call saves the running code and runs code against subject. A tail
+    // call replaces the running code instead unless the product has to be
+    // verified.
+    fn call(&mut self,
+            ctx: &Interpreter,
+            code: Rc<Code>,
+            subject: Noun,
+            verify: Option<(String, Noun, Noun)>,
+            tail: bool)
+            -> Result<(), NockError> {
+        if tail && verify.is_none() {
+            // The running code has nothing left to do but restore its
+            // subjects.
+            self.subjects.truncate(self.base);
+            self.code = code;
+            self.subject = subject;
+            self.pc = 0;
+            return Ok(());
+        }
+        try!(self.check_depth(ctx));
+        let frame = Frame {
+            code: mem::replace(&mut self.code, code),
+            pc: self.pc,
+            subject: mem::replace(&mut self.subject, subject),
+            base: mem::replace(&mut self.base, self.subjects.len()),
+            verify: verify,
+        };
+        self.frames.push(frame);
+        self.pc = 0;
+        Ok(())
+    }
+
+    // eval computes the formula on top of the stack against the subject
+    // under it.
+    fn eval(&mut self, ctx: &mut Interpreter, tail: bool) -> Result<(), NockError> {
+        let formula = self.pop();
+        let subject = self.pop();
+        let code = ctx.code(&formula);
+        self.call(ctx, code, subject, None, tail)
+    }
+
+    // arm computes the arm at axis of the core on top of the stack or runs
+    // its jet.
+    fn arm(&mut self, ctx: &mut Interpreter, axis: &Atom, tail: bool) -> Result<(), NockError> {
+        let core = self.pop();
+        let arm = try!(nock::arm(&core, axis));
+        let mut verify = None;
+        if let Some((name, jet)) = ctx.jets.find(&core, axis) {
+            let sample = try!(nock::fas(&core, &Atom::Direct(6)));
+            if let Some(jetted) = jet(&sample) {
+                if !ctx.jets.is_verifying() {
+                    self.stack.push(jetted);
+                    return Ok(());
+                }
+                verify = Some((name, sample, jetted));
+            }
+        }
+        let code = ctx.code(&arm);
+        self.call(ctx, code, core, verify, tail)
+    }
+
+    // check_depth makes room for one more frame or saved subject.
+    fn check_depth(&mut self, ctx: &Interpreter) -> Result<(), NockError> {
+        let frames = self.frames.len() + self.subjects.len();
+        if frames >= ctx.stack_limit {
+            return Err(NockError::new(Bail::Oom { frames: frames }));
+        }
+        self.depth = cmp::max(self.depth, frames + 1);
+        Ok(())
+    }
+
+    fn run(&mut self, ctx: &mut Interpreter, limits: Limits) -> Result<Noun, NockError> {
+        loop {
+            if self.pc == self.code.ops.len() {
+                let frame = match self.frames.pop() {
+                    Some(frame) => frame,
+                    None => return Ok(self.pop()),
+                };
+                if let Some((name, sample, jetted)) = frame.verify {
+                    let product = self.stack.last().expect("The VM stack underflowed");
+                    if jetted != *product {
+                        ctx.jets.mismatch(Mismatch {
+                            name: name,
+                            sample: sample,
+                            jet: jetted,
+                            nock: product.clone(),
+                        });
+                    }
+                }
+                self.code = frame.code;
+                self.pc = frame.pc;
+                self.subject = frame.subject;
+                self.base = frame.base;
+                continue;
+            }
+            if limits.max_steps.map_or(false, |max| self.steps >= max) {
+                return Err(NockError::new(Bail::Fuel { steps: self.steps }));
+            }
+            if let Some(ref flag) = ctx.interrupt {
+                if flag.swap(false, Ordering::SeqCst) {
+                    return Err(NockError::new(Bail::Intr { steps: self.steps }));
+                }
+            }
+            self.steps += 1;
+            let code = self.code.clone();
+            self.pc += 1;
+            match code.ops[self.pc - 1] {
+                Op::Slot(ref axis) => {
+                    let noun = try!(nock::fas(&self.subject, axis));
+                    self.stack.push(noun);
+                }
+                Op::Const(ref noun) => self.stack.push(noun.clone()),
+                Op::Cons => {
+                    let tail = self.pop();
+                    let head = self.pop();
+                    self.stack.push(Noun::cons(head, tail));
+                }
+                Op::Wut => {
+                    let noun = self.pop();
+                    self.stack.push(nock::wut(noun));
+                }
+                Op::Lus => {
+                    let noun = self.pop();
+                    self.stack.push(try!(nock::lus(noun)));
+                }
+                Op::Tis => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(if left == right { atom(0) } else { atom(1) });
+                }
+                Op::TisCell => {
+                    let noun = self.pop();
+                    self.stack.push(try!(nock::tis(noun)));
+                }
+                Op::Eval => try!(self.eval(ctx, false)),
+                Op::TailEval => try!(self.eval(ctx, true)),
+                Op::Enter => {
+                    try!(self.check_depth(ctx));
+                    let subject = self.pop();
+                    let saved = mem::replace(&mut self.subject, subject);
+                    self.subjects.push(saved);
+                }
+                Op::Pin => {
+                    try!(self.check_depth(ctx));
+                    let pinned = self.pop();
+                    let subject = Noun::cons(pinned, self.subject.clone());
+                    let saved = mem::replace(&mut self.subject, subject);
+                    self.subjects.push(saved);
+                }
+                Op::Leave => self.subject = self.subjects.pop().expect("Leave without an Enter"),
+                Op::Branch(to) => {
+                    let test = self.pop();
+                    match nock::loobean(&test) {
+                        Some(true) => (),
+                        Some(false) => self.pc = to,
+                        None => return Err(NockError::from(Exit::Loobean(test))),
+                    }
+                }
+                Op::Jump(to) => self.pc = to,
+                Op::Call(ref axis) => try!(self.arm(ctx, axis, false)),
+                Op::TailCall(ref axis) => try!(self.arm(ctx, axis, true)),
+                Op::Edit(ref axis) => {
+                    let target = self.pop();
+                    let value = self.pop();
+                    self.stack.push(try!(nock::hax(axis, value, &target)));
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Fast => {
+                    let core = self.pop();
+                    let clue = self.pop();
+                    ctx.jets.fast(&clue, &core);
+                    self.stack.push(core);
+                }
+                Op::MemoGet(ref formula, to) => {
+                    if let Some(product) = ctx.memo.get(&self.subject, formula) {
+                        self.stack.push(product);
+                        self.pc = to;
+                    }
+                }
+                Op::MemoPut(ref formula) => {
+                    let product = self.stack.last().expect("The VM stack underflowed").clone();
+                    ctx.memo.insert(self.subject.clone(), formula.clone(), product);
+                }
+                Op::TracePush(hint) => {
+                    let clue = self.pop();
+                    self.trace.push((hint, clue));
+                }
+                Op::TracePop => {
+                    self.trace.pop();
+                }
+                Op::Scry => {
+                    let path = self.pop();
+                    let reference = self.pop();
+                    match ctx.namespace.scry(&reference, &path) {
+                        Some(noun) => self.stack.push(noun),
+                        None => {
+                            return Err(NockError::from(Bail::Blocked {
+                                reference: reference,
+                                path: path,
+                            }))
+                        }
+                    }
+                }
+                Op::Crash(ref exit) => return Err(NockError::from(exit.clone())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use atom::Atom;
+    use bail::{Bail, Exit};
+    use jets::Jets;
+    use nock::{Interpreter, Limits, Mode, Spec};
+    use parser::{Noun, Parser, atom};
+    use tokenizer::tokenizer_tests::MockReader;
+    use vm::{Op, Vm, compile};
+
+    fn parse(expr: &str) -> Noun {
+        let reader = MockReader::new(vec![expr.to_string()]);
+        Parser::new(Box::new(reader)).parse().unwrap()
+    }
+
+    fn interpreter(mode: Mode) -> Interpreter {
+        let mut interpreter = Interpreter::new(Spec::Nock4K);
+        interpreter.set_mode(mode);
+        interpreter
+    }
+
+    #[test]
+    fn test_compile() {
+        let code = compile(&parse("[6 [5 [0 2] 1 0] [4 0 3] [0 1] 1 7]"), Spec::Nock4K);
+        assert_eq!(code.ops(),
+                   &[Op::Slot(Atom::Direct(2)),
+                     Op::Const(atom(0)),
+                     Op::Tis,
+                     Op::Branch(7),
+                     Op::Slot(Atom::Direct(3)),
+                     Op::Lus,
+                     Op::Jump(10),
+                     Op::Slot(Atom::Direct(1)),
+                     Op::Const(atom(7)),
+                     Op::Cons]);
+        let code = compile(&parse("[8 [1 0] 11 1869440365 7 [0 3] 0 1]"), Spec::Nock4K);
+        assert_eq!(code.ops(),
+                   &[Op::Const(atom(0)),
+                     Op::Pin,
+                     Op::MemoGet(parse("[7 [0 3] 0 1]"), 8),
+                     Op::Slot(Atom::Direct(3)),
+                     Op::Enter,
+                     Op::Slot(Atom::Direct(1)),
+                     Op::Leave,
+                     Op::MemoPut(parse("[7 [0 3] 0 1]")),
+                     Op::Leave]);
+        // Calls whose product the code returns are tail calls.
+        let code = compile(&parse("[8 [1 0] 6 [0 2] [2 [0 1] 0 3] 4 9 2 0 1]"), Spec::Nock4K);
+        assert_eq!(code.ops(),
+                   &[Op::Const(atom(0)),
+                     Op::Pin,
+                     Op::Slot(Atom::Direct(2)),
+                     Op::Branch(8),
+                     Op::Slot(Atom::Direct(1)),
+                     Op::Slot(Atom::Direct(3)),
+                     Op::TailEval,
+                     Op::Jump(11),
+                     Op::Slot(Atom::Direct(1)),
+                     Op::Call(Atom::Direct(2)),
+                     Op::Lus,
+                     Op::Leave]);
+        // Malformed formulas only crash when they're reached.
+        let code = compile(&parse("[6 [1 0] [1 1] 99 0]"), Spec::Nock4K);
+        assert_eq!(code.ops()[4],
+                   Op::Crash(Exit::Opcode {
+                       opcode: atom(99),
+                       formula: parse("[99 0]"),
+                   }));
+        assert_eq!(interpreter(Mode::Bytecode).compute(parse("[42 [6 [1 0] [1 1] 99 0]]")).unwrap(),
+                   atom(1));
+    }
+
+    #[test]
+    fn test_compute() {
+        let cases = vec![
+            "[[[4 5] [6 14 15]] [0 7]]",
+            "[77 [2 [1 42] [1 1 153 218]]]",
+            "[[132 19] [3 0 1]]",
+            "[[19 19] [5 [0 2] [0 3]]]",
+            "[42 [[4 0 1] [3 0 1]]]",
+            "[42 [6 [1 0] [4 0 1] [1 233]]]",
+            "[42 [6 [1 1] [4 0 1] [1 233]]]",
+            "[42 [7 [4 0 1] [4 0 1]]]",
+            "[42 [8 [4 0 1] [0 1]]]",
+            "[[[0 1] 42] [9 2 0 1]]",
+            "[[1 2] [10 [2 1 7] 0 1]]",
+            "[42 [11 1 [4 0 1]]]",
+            "[42 [11 [1 [4 0 1]] [4 0 1]]]",
+            // A dec gate counting up to 10.
+            "[[[8 [1 0] 8 [1 6 [5 [0 30] 4 0 6] [0 6] 9 2 10 [6 4 0 6] 0 1] 9 2 0 1] 10 0] [9 2 0 1]]",
+        ];
+        for expr in cases {
+            let tree = interpreter(Mode::Tree).compute(parse(expr)).unwrap();
+            assert_eq!(interpreter(Mode::Bytecode).compute(parse(expr)).unwrap(), tree, "{}", expr);
+            assert_eq!(interpreter(Mode::Check).compute(parse(expr)).unwrap(), tree, "{}", expr);
+        }
+        let mut legacy = Interpreter::new(Spec::Nock5K);
+        let mut compiled = Interpreter::new(Spec::Nock5K);
+        compiled.set_mode(Mode::Bytecode);
+        for expr in vec!["[[19 19] [5 [0 1]]]", "[42 [10 [1 [4 0 1]] [4 0 1]]]"] {
+            assert_eq!(compiled.compute(parse(expr)).unwrap(), legacy.compute(parse(expr)).unwrap());
+        }
+        assert!(compiled.compute(parse("[42 [11 1 [4 0 1]]]")).is_err());
+    }
+
+    #[test]
+    fn test_compute_hints() {
+        let mut vm = interpreter(Mode::Bytecode);
+        // %memo caches *[42 4 0 1].
+        let memo = parse("[42 [11 [1869440365 1 0] 4 0 1]]");
+        assert_eq!(vm.compute(memo.clone()).unwrap(), atom(43));
+        assert_eq!(vm.compute(memo).unwrap(), atom(43));
+        assert_eq!(vm.memo().stats().hits, 1);
+        // %fast names the gate for its jet.
+        vm.set_jets(Jets::with_defaults());
+        let battery = "[8 [1 0] 8 [1 6 [5 [0 30] 4 0 6] [0 6] 9 2 10 [6 4 0 6] 0 1] 9 2 0 1]";
+        let dec = format!("[[{} 1000 0] [7 [11 [1953718630 1 6514020 0] 0 1] 9 2 0 1]]", battery);
+        let computed = vm.compute_with_limits(parse(&dec), Limits::default()).unwrap();
+        assert_eq!(computed.product, atom(999));
+        assert!(computed.steps < 20);
+        // %spot is printed under the crash.
+        let spot = "[1953460339 1 [7303014 1852796776 0] [1 2] 3 4]";
+        let err = vm.compute(parse(&format!("[42 [11 {} [0 2]]]", spot))).unwrap_err();
+        assert_eq!(err.trace(), &["/foo/hoon:<[1 2].[3 4]>".to_string()]);
+
+        // Checking keeps only the walk's memo entries, names and mismatches.
+        let mut check = interpreter(Mode::Check);
+        assert_eq!(check.compute(parse("[42 [11 [1869440365 1 0] 4 0 1]]")).unwrap(), atom(43));
+        let stats = check.memo().stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 0, 1));
+        let mut jets = Jets::new();
+        jets.register("dec", |_| Some(atom(7)));
+        jets.set_verify(true);
+        check.set_jets(jets);
+        assert_eq!(check.compute(parse(&dec)).unwrap(), atom(999));
+        assert_eq!(check.jets().take_mismatches().len(), 1);
+    }
+
+    #[test]
+    fn test_compute_crashes() {
+        let mut vm = interpreter(Mode::Bytecode);
+        let bail = |vm: &mut Interpreter, expr: &str| vm.compute(parse(expr)).unwrap_err().bail().clone();
+        assert_eq!(bail(&mut vm, "[42 [0 2]]"),
+                   Bail::Exit(Exit::Slot {
+                       axis: Atom::Direct(2),
+                       noun: atom(42),
+                   }));
+        assert_eq!(bail(&mut vm, "[42 [6 [1 2] [1 0] 1 1]]"), Bail::Exit(Exit::Loobean(atom(2))));
+        assert_eq!(bail(&mut vm, "[42 [12 [1 0] [1 1 2 0]]]"),
+                   Bail::Blocked {
+                       reference: atom(0),
+                       path: parse("[1 2 0]"),
+                   });
+        let forever = parse("[[2 [0 1] 0 1] [2 [0 1] 0 1]]");
+        let limits = Limits { max_steps: Some(1000) };
+        assert!(vm.compute_with_limits(forever.clone(), limits).unwrap_err().is_out_of_fuel());
+        vm.set_stack_limit(100);
+        assert_eq!(bail(&mut vm, "[[4 2 [0 1] 0 1] [4 2 [0 1] 0 1]]"), Bail::Oom { frames: 100 });
+    }
+
+    #[test]
+    fn test_compute_tail_calls() {
+        // Counting loops through 9 and 8, through 2 and through 7 all run in
+        // a handful of frames.
+        let battery = "[8 [1 0] 8 [1 6 [5 [0 30] 4 0 6] [0 6] 9 2 10 [6 4 0 6] 0 1] 9 2 0 1]";
+        let count = "[6 [5 [0 2] [1 100000]] [0 2] [2 [[4 0 2] [0 3]] [0 3]]]";
+        let compose = "[6 [5 [0 2] [1 100000]] [0 2] [7 [[4 0 2] [0 3]] [2 [0 1] [0 3]]]]";
+        let cases = vec![
+            (format!("[[{} 100000 0] [9 2 0 1]]", battery), atom(99999)),
+            (format!("[[0 {}] {}]", count, count), atom(100000)),
+            (format!("[[0 {}] {}]", compose, compose), atom(100000)),
+        ];
+        for mode in vec![Mode::Tree, Mode::Bytecode] {
+            let mut interpreter = interpreter(mode);
+            interpreter.set_stack_limit(16);
+            for &(ref expr, ref expected) in cases.iter() {
+                assert_eq!(interpreter.compute(parse(expr)).unwrap(), *expected, "{} {}", mode, expr);
+            }
+        }
+        // The VM's frames and saved subjects never grow past what one
+        // iteration needs.
+        let mut ctx = interpreter(Mode::Bytecode);
+        for &(ref expr, ref expected) in cases.iter() {
+            let mut vm = Vm::new(&mut ctx, parse(expr));
+            assert_eq!(vm.run(&mut ctx, Limits::default()).unwrap(), *expected, "{}", expr);
+            assert!(vm.depth <= 3, "{} reached depth {}", expr, vm.depth);
+            assert!(vm.frames.is_empty() && vm.subjects.is_empty(), "{}", expr);
+        }
+        // Without the tail call the same loop holds a frame per iteration.
+        let deep = "[6 [5 [0 2] [1 1000]] [0 2] [4 2 [[4 0 2] [0 3]] [0 3]]]";
+        let mut vm = Vm::new(&mut ctx, parse(&format!("[[0 {}] {}]", deep, deep)));
+        assert_eq!(vm.run(&mut ctx, Limits::default()).unwrap(), atom(2000));
+        assert!(vm.depth >= 1000);
+    }
+
+    #[test]
+    fn test_compute_deep() {
+        // A formula nested a million autocons deep compiles and runs without
+        // overflowing the native stack.
+        let mut formula = cell!(atom(0), atom(1));
+        for _ in 0..1000000 {
+            formula = cell!(cell!(atom(0), atom(1)), formula);
+        }
+        let mut vm = interpreter(Mode::Bytecode);
+        assert!(vm.compute(cell!(atom(42), formula)).is_ok());
+    }
+}